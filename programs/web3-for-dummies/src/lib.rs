@@ -1,26 +1,20 @@
-use anchor_lang::{prelude::*, system_program};
+use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
     token_interface::{
-        self, // Using the newer token_interface standard
         transfer_checked, // For transfers that check mint decimals
         Mint,
         TokenAccount,
         TokenInterface,
         TransferChecked, // Struct for transfer_checked CPI
-        MintTo, // Struct for mint_to CPI (not used here, but good practice)
-        mint_to, // Function for mint_to CPI (not used here)
-        Burn, // Struct for burn CPI (not used here)
-        burn // Function for burn CPI (not used here)
+        MintTo, // Struct for mint_to CPI (used to issue LP shares)
+        mint_to, // Function for mint_to CPI (used to issue LP shares)
+        Burn, // Struct for burn CPI (used to redeem LP shares)
+        burn // Function for burn CPI (used to redeem LP shares)
     },
 
 };
 
-// Required for getting mutable access to account data, e.g., pool state
-use std::ops::DerefMut;
-// Required for min/max functions used in PDA seed generation
-use std::cmp::{max, min};
-
 
 // Declare the program's on-chain address (ID)
 declare_id!("B53vYkHSs1vMQzofYfKjz6Unzv8P4TwCcvvTbMWVnctv");
@@ -30,19 +24,131 @@ pub mod web3_for_dummies {
 
     use super::*; // Imports items from the outer scope (like structs, errors, etc.)
 
+    /// Creates the program-wide `ProgramConfig` singleton. Called once after deployment by the
+    /// program's upgrade authority, which becomes the config admin (and can hand the role over
+    /// with `propose_admin` / `accept_admin`).
+    /// `fee_tiers_bps` lists the swap fees pools may charge and `default_fee_bps` (one of them)
+    /// is used when `initialize_pool` doesn't pick one. `protocol_fee_share_bps` of every swap fee
+    /// is set aside for the protocol, and collected fees can only go to `treasury`'s token accounts.
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        default_fee_bps: u16,
+        protocol_fee_share_bps: u16,
+        treasury: Pubkey,
+        fee_tiers_bps: Vec<u16>,
+    ) -> Result<()> {
+        // Every tier has to be a valid fee and the list has to fit the account
+        if fee_tiers_bps.is_empty() || fee_tiers_bps.len() > MAX_FEE_TIERS {
+            return err!(SwapError::InvalidFeeTier);
+        }
+        if fee_tiers_bps.iter().any(|&tier| tier as u128 >= BPS_DENOMINATOR) {
+            return err!(SwapError::InvalidFee);
+        }
+        if !fee_tiers_bps.contains(&default_fee_bps) {
+            return err!(SwapError::InvalidFeeTier);
+        }
+        // The protocol can take at most the whole swap fee
+        if protocol_fee_share_bps as u128 > BPS_DENOMINATOR {
+            return err!(SwapError::InvalidFee);
+        }
+
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.pending_admin = None;
+        config.treasury = treasury;
+        config.default_fee_bps = default_fee_bps;
+        config.protocol_fee_share_bps = protocol_fee_share_bps;
+        config.fee_tiers_bps = fee_tiers_bps;
+        config.paused = false;
+        config.bump = ctx.bumps.config;
+
+        msg!("Config Initialized!");
+        msg!("Admin: {}", config.admin);
+        msg!("Treasury: {}", config.treasury);
+        msg!("Default Fee (bps): {}", config.default_fee_bps);
+        msg!("Protocol Fee Share (bps): {}", config.protocol_fee_share_bps);
+        msg!("Fee Tiers (bps): {:?}", config.fee_tiers_bps);
+
+        Ok(())
+    }
+
     /// Initializes a new liquidity pool with the given token mints.
-    /// Creates the pool state account and associated token accounts (vaults) to hold the tokens.
-    pub fn initialize_pool(ctx: Context<InitializePool>) -> Result<()> {
+    /// Creates the pool state account, the associated token accounts (vaults) to hold the tokens,
+    /// and the LP mint whose tokens represent a depositor's share of the pool.
+    /// The swap fee is `fee_bps` of every input amount (e.g. 30 = 0.3%) and has to be one of the
+    /// config's fee tiers; `None` uses the config's default fee. The config's protocol fee share
+    /// of every swap fee is set aside for the protocol treasury.
+    /// Protocol fees and the pool's settings are managed by the config admin, not the initializer.
+    /// `curve_type` picks the pricing curve; `amp` is the amplification coefficient for
+    /// StableSwap pools and is ignored for other curves. `token_a_weight_bps` is the weight
+    /// of the `token_a_mint` account for Weighted pools (e.g. 8000 for an 80/20 pool); the
+    /// other token gets the remainder. It is ignored for other curves.
+    /// `fee_mode` picks between the fixed fee above and a dynamic fee that follows recent volatility.
+    /// A dynamic range's bounds have to be fee tiers themselves and bracket `fee_bps`, so the tier
+    /// a pool is seeded with stays representative of what it charges.
+    /// `max_price_impact_bps` caps the price impact of a single swap (zero disables the cap);
+    /// the config admin can change it later with `set_max_price_impact`.
+    /// The initializer seeds the pool with `initial_amount_a` of the `token_a_mint` account and
+    /// `initial_amount_b` of the `token_b_mint` account and receives the first LP shares, so the
+    /// starting price is set in the same instruction that creates the pool. `MINIMUM_LIQUIDITY`
+    /// of those shares are locked in a pool-owned account for good.
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize_pool(
+        ctx: Context<InitializePool>,
+        fee_bps: Option<u16>,
+        curve_type: CurveType,
+        amp: u64,
+        token_a_weight_bps: u16,
+        fee_mode: FeeMode,
+        max_price_impact_bps: u16,
+        initial_amount_a: u64,
+        initial_amount_b: u64,
+    ) -> Result<()> {
+        if initial_amount_a == 0 || initial_amount_b == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        // --- Read Defaults From Config ---
+        let config = &ctx.accounts.config;
+        let fee_bps = fee_bps.unwrap_or(config.default_fee_bps);
+        // Validate the fee: it must be below 100% and one of the allowed tiers
+        if fee_bps as u128 >= BPS_DENOMINATOR {
+            return err!(SwapError::InvalidFee);
+        }
+        if !config.fee_tiers_bps.contains(&fee_bps) {
+            return err!(SwapError::InvalidFeeTier);
+        }
+        let protocol_fee_share_bps = config.protocol_fee_share_bps;
+        // A dynamic fee needs an ordered range of fee tiers around the pool's tier
+        if let FeeMode::Dynamic { min_fee_bps, max_fee_bps } = fee_mode {
+            if min_fee_bps > max_fee_bps || max_fee_bps as u128 >= BPS_DENOMINATOR {
+                return err!(SwapError::InvalidFee);
+            }
+            if !(min_fee_bps..=max_fee_bps).contains(&fee_bps)
+                || !config.fee_tiers_bps.contains(&min_fee_bps)
+                || !config.fee_tiers_bps.contains(&max_fee_bps)
+            {
+                return err!(SwapError::InvalidFeeTier);
+            }
+        }
+        if max_price_impact_bps as u128 > BPS_DENOMINATOR {
+            return err!(SwapError::InvalidPriceImpactLimit);
+        }
+        // StableSwap pools need an amplification coefficient in range
+        if curve_type == CurveType::StableSwap && !(MIN_AMP..=MAX_AMP).contains(&amp) {
+            return err!(SwapError::InvalidCurveParameters);
+        }
+        // Weighted pools need both weights to be at least MIN_WEIGHT_BPS
+        if curve_type == CurveType::Weighted
+            && !(MIN_WEIGHT_BPS..=BPS_DENOMINATOR as u16 - MIN_WEIGHT_BPS).contains(&token_a_weight_bps)
+        {
+            return err!(SwapError::InvalidCurveParameters);
+        }
+
         // Get mutable access to the newly created pool account
         let pool = &mut ctx.accounts.pool;
 
-        let (smaller_mint, larger_mint) = if ctx.accounts.token_a_mint.key() < ctx.accounts.token_b_mint.key() {
-            (ctx.accounts.token_a_mint.key(), ctx.accounts.token_b_mint.key())
-            
-        } else {
-            (ctx.accounts.token_b_mint.key(), ctx.accounts.token_a_mint.key())            
-
-        };
+        let (smaller_mint, larger_mint) = canonical_mint_order(ctx.accounts.token_a_mint.key(), ctx.accounts.token_b_mint.key());
 
         pool.token_a_mint = smaller_mint;
 
@@ -55,14 +161,135 @@ pub mod web3_for_dummies {
             pool.token_a_vault = ctx.accounts.token_b_vault.key();
             pool.token_b_vault = ctx.accounts.token_a_vault.key();
         }
-        // Store the public keys of the token mints and vaults in the pool state
-        // pool.token_a_mint = ctx.accounts.token_a_mint.key();
-        // pool.token_b_mint = ctx.accounts.token_b_mint.key();
-        // pool.token_a_vault = ctx.accounts.token_a_vault.key();
-        // pool.token_b_vault = ctx.accounts.token_b_vault.key();
-        // Store the bump seed for the pool's PDA, needed for signing CPIs later
+        // Store the bump seed for the pool's PDA
         // Use the bump specific to the 'pool' account derivation
         pool.bump = ctx.bumps.pool; // Anchor still provides the bump used for init
+        // The vault authority has its own PDA and signs CPIs, so token ownership
+        // doesn't depend on the pool's seeds or account layout
+        pool.authority_bump = ctx.bumps.pool_authority;
+        // Store the LP mint so deposits can be checked against it later
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.fee_tier_bps = fee_bps;
+        pool.fee_numerator = fee_bps as u64;
+        pool.fee_denominator = BPS_DENOMINATOR as u64;
+        pool.protocol_fee_share_bps = protocol_fee_share_bps;
+        pool.fee_mode = fee_mode;
+        pool.max_price_impact_bps = max_price_impact_bps;
+        pool.paused = false;
+        pool.curve_type = curve_type;
+        pool.amp = if curve_type == CurveType::StableSwap { amp } else { 0 };
+        // Decimals are stored in pool order so StableSwap math can normalize balances
+        if ctx.accounts.token_a_mint.key() == smaller_mint {
+            pool.token_a_decimals = ctx.accounts.token_a_mint.decimals;
+            pool.token_b_decimals = ctx.accounts.token_b_mint.decimals;
+        } else {
+            pool.token_a_decimals = ctx.accounts.token_b_mint.decimals;
+            pool.token_b_decimals = ctx.accounts.token_a_mint.decimals;
+        }
+        // Weights are also stored in pool order; non-weighted pools keep zeros
+        if curve_type == CurveType::Weighted {
+            let other_weight_bps = BPS_DENOMINATOR as u16 - token_a_weight_bps;
+            if ctx.accounts.token_a_mint.key() == smaller_mint {
+                pool.token_a_weight_bps = token_a_weight_bps;
+                pool.token_b_weight_bps = other_weight_bps;
+            } else {
+                pool.token_a_weight_bps = other_weight_bps;
+                pool.token_b_weight_bps = token_a_weight_bps;
+            }
+        }
+
+        // Start the oracle's history at pool creation
+        let now = Clock::get()?.unix_timestamp;
+        pool.last_oracle_update = now;
+        pool.last_volatility_update = now;
+        let oracle = &mut ctx.accounts.oracle;
+        oracle.pool = pool.key();
+        oracle.record(Observation {
+            timestamp: now,
+            ..Default::default()
+        });
+
+        // --- Seed Liquidity ---
+        // The initial amounts follow the mint accounts as passed; the pool stores them sorted
+        let (amount_a, amount_b) = if ctx.accounts.token_a_mint.key() == smaller_mint {
+            (initial_amount_a, initial_amount_b)
+        } else {
+            (initial_amount_b, initial_amount_a)
+        };
+        let total_lp_amount = pool.lp_shares_for_deposit(amount_a, amount_b, 0, 0, 0)?;
+        // Permanently lock the first MINIMUM_LIQUIDITY shares, so the share price can't be
+        // inflated by donating to a nearly empty pool
+        if total_lp_amount <= MINIMUM_LIQUIDITY {
+            return err!(SwapError::InsufficientInitialLiquidity);
+        }
+        let lp_amount = total_lp_amount - MINIMUM_LIQUIDITY;
+        // The oracle prices from these until the first trade or liquidity change
+        pool.reserve_a = amount_a;
+        pool.reserve_b = amount_b;
+        // Starting price of Token A in Token B, in whole tokens
+        let (price_numerator, price_denominator) = pool.spot_price(amount_a, amount_b, true)?;
+        let initial_price = to_fixed_point_price(
+            price_numerator,
+            price_denominator,
+            pool.token_a_decimals,
+            pool.token_b_decimals,
+        )?;
+        let initial_price = u128::try_from(initial_price).map_err(|_| error!(SwapError::CalculationOverflow))?;
+
+        // Transfer token A
+        let transfer_a_accounts = TransferChecked {
+            from: ctx.accounts.initializer_token_a_account.to_account_info(),
+            mint: ctx.accounts.token_a_mint.to_account_info(),
+            to: ctx.accounts.token_a_vault.to_account_info(),
+            authority: ctx.accounts.initializer.to_account_info(),
+        };
+        let transfer_a_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_a_accounts,
+        );
+        transfer_checked(transfer_a_cpi, initial_amount_a, ctx.accounts.token_a_mint.decimals)?;
+
+        // Transfer token B
+        let transfer_b_accounts = TransferChecked {
+            from: ctx.accounts.initializer_token_b_account.to_account_info(),
+            mint: ctx.accounts.token_b_mint.to_account_info(),
+            to: ctx.accounts.token_b_vault.to_account_info(),
+            authority: ctx.accounts.initializer.to_account_info(),
+        };
+        let transfer_b_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_b_accounts,
+        );
+        transfer_checked(transfer_b_cpi, initial_amount_b, ctx.accounts.token_b_mint.decimals)?;
+
+        // Mint the first LP shares: Pool LP Mint -> Locked LP Account and Initializer
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        let lock_lp_accounts = MintTo {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.locked_lp_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let lock_lp_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            lock_lp_accounts,
+            signer,
+        );
+        mint_to(lock_lp_cpi, MINIMUM_LIQUIDITY)?;
+
+        let mint_lp_accounts = MintTo {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.initializer_lp_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let mint_lp_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            mint_lp_accounts,
+            signer,
+        );
+        mint_to(mint_lp_cpi, lp_amount)?;
 
         // Log the details of the initialized pool (useful for debugging)
         msg!("Pool Initialized!");
@@ -70,70 +297,72 @@ pub mod web3_for_dummies {
         msg!("Mint B: {}", pool.token_b_mint);
         msg!("Vault A: {}", pool.token_a_vault);
         msg!("Vault B: {}", pool.token_b_vault);
+        msg!("LP Mint: {}", pool.lp_mint);
+        msg!("Fee: {}/{}", pool.fee_numerator, pool.fee_denominator);
+        msg!("Protocol Fee Share (bps): {}", pool.protocol_fee_share_bps);
+        msg!("Fee Mode: {:?}", pool.fee_mode);
+        msg!("Max Price Impact (bps): {}", pool.max_price_impact_bps);
+        msg!("Curve: {:?} (amp: {})", pool.curve_type, pool.amp);
+        msg!("Weights (bps): {}/{}", pool.token_a_weight_bps, pool.token_b_weight_bps);
         msg!("Pool Bump: {}", pool.bump);
+        msg!("Initial Reserves: {}/{} (LP shares: {}, locked: {})", amount_a, amount_b, lp_amount, MINIMUM_LIQUIDITY);
+
+        emit!(PoolCreatedEvent {
+            pool: pool.key(),
+            creator: ctx.accounts.initializer.key(),
+            token_a_mint: pool.token_a_mint,
+            token_b_mint: pool.token_b_mint,
+            fee_tier_bps: pool.fee_tier_bps,
+            curve_type: pool.curve_type,
+            amount_a,
+            amount_b,
+            lp_amount,
+            initial_price,
+        });
 
+        // --- Register The Pool ---
+        // Index the pool under both mints so clients can enumerate pools on-chain
+        let pool_key = pool.key();
+        let accounts = &mut *ctx.accounts;
+        let bumps = &ctx.bumps;
+        for (index, index_bump, page, page_bump, mint) in [
+            (
+                &mut accounts.token_a_pool_index, bumps.token_a_pool_index,
+                &mut accounts.token_a_pool_index_page, bumps.token_a_pool_index_page,
+                accounts.token_a_mint.key(),
+            ),
+            (
+                &mut accounts.token_b_pool_index, bumps.token_b_pool_index,
+                &mut accounts.token_b_pool_index_page, bumps.token_b_pool_index_page,
+                accounts.token_b_mint.key(),
+            ),
+        ] {
+            register_pool(index, index_bump, page, page_bump, mint, pool_key)?;
+        }
 
         Ok(()) // Indicate successful execution
     }
 
-    /// Swaps one token for another using the constant product formula.
+    /// Swaps one token for another on the pool's curve (constant product, StableSwap or Weighted).
     /// Requires the amount of token to send in and the minimum amount of token expected out (slippage protection).
-    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64) -> Result<()> {
-        // Get immutable access to the pool state
-        let pool = &ctx.accounts.pool;
+    /// An optional `deadline` (unix timestamp) rejects the swap if it lands too late.
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64, deadline: Option<i64>) -> Result<()> {
+        // Reject stale transactions before looking at prices
+        check_deadline(deadline)?;
 
         // --- Input Validation ---
-        // Ensure the user's source token account mint matches one of the pool's tokens
-        // This check is partially redundant due to constraints but good for clarity
-        if ctx.accounts.user_source_token_account.mint != pool.token_a_mint && ctx.accounts.user_source_token_account.mint != pool.token_b_mint {
-            return err!(SwapError::InvalidMint);
-        }
-
-        
-
-        // --- Determine Source/Destination Vaults ---
-        // Figure out which pool vault receives tokens (source) and which sends tokens (destination)
-        // based on the mint of the user's source token account.
-        // Also retrieve the decimals of the source mint for transfer_checked.
-        let (source_vault_account, dest_vault_account, source_mint_decimals) = {
-            if ctx.accounts.user_source_token_account.mint == pool.token_a_mint {
-                // User is sending Token A, wants Token B
-                (
-                    &mut ctx.accounts.token_a_vault, // Pool's vault A is the source
-                    &mut ctx.accounts.token_b_vault, // Pool's vault B is the destination
-                    ctx.accounts.source_mint.decimals, // Decimals of Token A
-                )
-            } else {
-                // User is sending Token B, wants Token A (since we already validated the mint)
-                (
-                    &mut ctx.accounts.token_b_vault, // Pool's vault B is the source
-                    &mut ctx.accounts.token_a_vault, // Pool's vault A is the destination
-                    ctx.accounts.source_mint.decimals, // Decimals of Token B
-                )
-            }
-        };
-
-
-        // --- Destination Mint Check ---
-        // Ensure the user's destination token account matches the mint of the pool's destination vault
-        // This check is partially redundant due to constraints but good for clarity
-        if ctx.accounts.user_destination_token_account.mint != dest_vault_account.mint {
-            return err!(SwapError::InvalidDestinationMint);
-        }
+        // Work out which way the swap goes from the user's source token account
+        let is_a_to_b = ctx.accounts.swap_direction()?;
 
         // --- Get Reserves ---
-        // Reload vault accounts to get the latest balance data on-chain
-        // It's crucial to reload *before* calculations to prevent race conditions.
-        source_vault_account.reload()?;
-        dest_vault_account.reload()?;
-        let reserve_in = source_vault_account.amount; // Current balance of the token being sent *in*
-        let reserve_out = dest_vault_account.amount; // Current balance of the token being sent *out*
+        let (reserve_in, reserve_out) = ctx.accounts.trading_reserves(is_a_to_b)?;
+
+        // --- Update Oracle ---
+        // Record the price the pool held up to now, before this swap moves it
+        ctx.accounts.oracle.update(&mut ctx.accounts.pool)?;
 
-        // --- Swap Calculation (Constant Product: x * y = k) ---
-        // Convert amounts to u128 for calculation to prevent intermediate overflows
-        let amount_in_u128 = amount_in as u128;
-        let reserve_in_u128 = reserve_in as u128;
-        let reserve_out_u128 = reserve_out as u128;
+        // A dynamic fee is priced off the volatility left over from earlier swaps
+        ctx.accounts.pool.decay_volatility(Clock::get()?.unix_timestamp);
 
         // Basic checks before calculation
         if reserve_in == 0 || reserve_out == 0 {
@@ -143,112 +372,181 @@ pub mod web3_for_dummies {
             return err!(SwapError::ZeroAmount); // Input amount must be positive
         }
 
-        let fee_numerator = 3;
-        let fee_denomiantor = 1000;
-        let amount_in_after_fee = amount_in_u128
-            .checked_mul(fee_denomiantor - fee_numerator)
-            .ok_or(SwapError::CalculationOverflow)?
-            .checked_div(fee_denomiantor)
-            .ok_or(SwapError::CalculationOverflow)?;
+        let pool = &ctx.accounts.pool;
+
+        // --- Swap Calculation ---
+        // The pool's fee is taken from amount_in before it is priced on the pool's curve,
+        // so the fee stays in the vault and the invariant grows with every swap.
+        let amount_out = pool.swap_output(amount_in, reserve_in, reserve_out, is_a_to_b)?;
+
+        // --- Slippage Check ---
+        // Ensure the calculated amount_out meets the user's minimum requirement
+        if amount_out < min_amount_out {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        let price_impact_bps = pool.price_impact_bps(amount_in, amount_out, reserve_in, reserve_out, is_a_to_b)?;
+        check_price_impact(price_impact_bps, pool.max_price_impact_bps)?;
+
+        // --- Perform Transfers via CPI ---
+        ctx.accounts.execute_swap(is_a_to_b, reserve_in, reserve_out, amount_in, amount_out, price_impact_bps)?;
+
+        Ok(()) // Indicate successful execution
+    }
+
+    /// Swaps one token for an exact amount of the other on the pool's curve.
+    /// Requires the amount of token to receive and the maximum amount of token the user is
+    /// willing to send in (slippage protection). Uses the same accounts as `swap`.
+    /// An optional `deadline` (unix timestamp) rejects the swap if it lands too late.
+    pub fn swap_exact_out(ctx: Context<Swap>, amount_out: u64, max_amount_in: u64, deadline: Option<i64>) -> Result<()> {
+        // Reject stale transactions before looking at prices
+        check_deadline(deadline)?;
+
+        // --- Input Validation ---
+        let is_a_to_b = ctx.accounts.swap_direction()?;
 
-        let constant_product = reserve_in_u128.checked_mul(reserve_out_u128).ok_or(SwapError::CalculationOverflow)?;
-        let new_reserve_in = reserve_in_u128.checked_add(amount_in_after_fee).ok_or(SwapError::CalculationOverflow)?;
+        // --- Get Reserves ---
+        let (reserve_in, reserve_out) = ctx.accounts.trading_reserves(is_a_to_b)?;
 
-        // Calculate the constant product (k)
-        // x * y = k
-        let constant_product = reserve_in_u128.checked_mul(reserve_out_u128).ok_or(SwapError::CalculationOverflow)?;
+        // --- Update Oracle ---
+        // Record the price the pool held up to now, before this swap moves it
+        ctx.accounts.oracle.update(&mut ctx.accounts.pool)?;
 
-        // Calculate the new reserve amount for the input token
-        // new_x = x + amount_in
-        let new_reserve_in = reserve_in_u128.checked_add(amount_in_u128).ok_or(SwapError::CalculationOverflow)?;
+        ctx.accounts.pool.decay_volatility(Clock::get()?.unix_timestamp);
 
-        // Calculate the new reserve amount for the output token based on k
-        // new_y = k / new_x
-        // Note: Integer division truncates, favoring the pool slightly.
-        let new_reserve_out = constant_product.checked_div(new_reserve_in).ok_or(SwapError::CalculationOverflow)?;
+        // Basic checks before calculation
+        if reserve_in == 0 || reserve_out == 0 {
+            return err!(SwapError::PoolIsEmpty);
+        }
+        if amount_out == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
 
-        // Calculate the amount of output tokens to send to the user
-        // amount_out = y - new_y
-        let amount_out_u128 = reserve_out_u128.checked_sub(new_reserve_out).ok_or(SwapError::CalculationOverflow)?;
+        let pool = &ctx.accounts.pool;
 
-        // Convert amount_out back to u64
-        let amount_out = amount_out_u128 as u64;
+        // --- Swap Calculation (solved for the input) ---
+        // Every step rounds up, so the user always pays at least what the curve requires.
+        let amount_in = pool.swap_input(amount_out, reserve_in, reserve_out, is_a_to_b)?;
 
         // --- Slippage Check ---
-        // Ensure the calculated amount_out meets the user's minimum requirement
-        if amount_out < min_amount_out {
+        // Ensure the required amount_in doesn't exceed the user's maximum
+        if amount_in > max_amount_in {
             return err!(SwapError::SlippageExceeded);
         }
 
-        let price_impact_bs = amount_out_u128
-        .checked_mul(10000)
-        .ok_or(SwapError::CalculationOverflow)?
-        .checked_div(reserve_out_u128)
-        .ok_or(SwapError::CalculationOverflow)?;
+        let price_impact_bps = pool.price_impact_bps(amount_in, amount_out, reserve_in, reserve_out, is_a_to_b)?;
+        check_price_impact(price_impact_bps, pool.max_price_impact_bps)?;
 
-        const MAX_PRICE_IMPACT_BPS: u128 = 1000;
-        if price_impact_bs > MAX_PRICE_IMPACT_BPS {
-            return err!(SwapError::ExcessivePriceImpact);
+        // --- Perform Transfers via CPI ---
+        ctx.accounts.execute_swap(is_a_to_b, reserve_in, reserve_out, amount_in, amount_out, price_impact_bps)?;
+
+        Ok(())
+    }
+
+    /// Swaps through several pools in one instruction, feeding each hop's output into the next.
+    /// `remaining_accounts` holds one `(pool, pool_authority, vault_a, vault_b, oracle)` group per hop,
+    /// in route order, followed by the mints of the `hops - 1` intermediate tokens.
+    /// Only the final output is checked against `min_amount_out`.
+    /// An optional `deadline` (unix timestamp) rejects the route if it lands too late.
+    pub fn route_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+        hops: u8,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        // Reject stale transactions before looking at prices
+        check_deadline(deadline)?;
+
+        let hop_count = hops as usize;
+        if hop_count == 0 || hop_count > MAX_ROUTE_HOPS {
+            return err!(SwapError::InvalidRoute);
+        }
+        let remaining = ctx.remaining_accounts;
+        if remaining.len() != hop_count * ROUTE_HOP_ACCOUNTS + (hop_count - 1) {
+            return err!(SwapError::InvalidRoute);
+        }
+        if amount_in == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        let (hop_accounts, intermediate_mints) = remaining.split_at(hop_count * ROUTE_HOP_ACCOUNTS);
+
+        // --- Quote Every Hop ---
+        // All quotes are taken from the pre-trade reserves, so the same pool can't appear twice.
+        let mut route: Vec<RouteHop<'info>> = Vec::with_capacity(hop_count);
+        let mut current_mint = ctx.accounts.source_mint.key();
+        let mut current_amount = amount_in;
+        for (index, group) in hop_accounts.chunks(ROUTE_HOP_ACCOUNTS).enumerate() {
+            let hop = RouteHop::load(group, current_mint, current_amount)?;
+            if route.iter().any(|previous| previous.pool.key() == hop.pool.key()) {
+                return err!(SwapError::InvalidRoute);
+            }
+            // Intermediate mints have to line up with the token each hop pays out
+            if index + 1 < hop_count && intermediate_mints[index].key() != hop.vault_out.mint {
+                return err!(SwapError::InvalidRoute);
+            }
+            current_mint = hop.vault_out.mint;
+            current_amount = hop.amount_out;
+            route.push(hop);
+        }
+
+        // The route has to end in the token the user wants
+        if current_mint != ctx.accounts.destination_mint.key() {
+            return err!(SwapError::InvalidDestinationMint);
+        }
+
+        // --- Slippage Check ---
+        // One check on the final output instead of one per hop
+        let amount_out = current_amount;
+        if amount_out < min_amount_out {
+            return err!(SwapError::SlippageExceeded);
         }
 
         // --- Perform Transfers via CPI ---
 
-        // 1. Transfer IN: User -> Pool Source Vault
+        // 1. Transfer IN: User -> First Pool's Input Vault
         let transfer_in_accounts = TransferChecked {
-            from: ctx.accounts.user_source_token_account.to_account_info(), // User's source ATA
-            mint: ctx.accounts.source_mint.to_account_info(), // Mint of the token being sent in
-            to: source_vault_account.to_account_info(), // Pool's vault for receiving the token
-            authority: ctx.accounts.user_authority.to_account_info(), // User signing the transaction
+            from: ctx.accounts.user_source_token_account.to_account_info(),
+            mint: ctx.accounts.source_mint.to_account_info(),
+            to: route[0].vault_in.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
         };
         let transfer_in_cpi = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(), // Target program (Token Program)
-            transfer_in_accounts, // Accounts required by transfer_checked
+            ctx.accounts.token_program.to_account_info(),
+            transfer_in_accounts,
         );
-        // Execute the CPI
-        transfer_checked(transfer_in_cpi, amount_in, source_mint_decimals)?;
-
-
-        // 2. Transfer OUT: Pool Destination Vault -> User
-        // Define the PDA signer seeds for the pool authority
-        // Use the bump stored in the pool account state
-        let pool_bump_slice = &[pool.bump];
-        let pool_signer_seeds: &[&[u8]] = &[
-            b"pool",
-            pool.token_a_mint.as_ref(),
-            pool.token_b_mint.as_ref(),
-            pool_bump_slice
-        ];
-        // Add another layer of &[&[u8]] for the signer seeds argument
-        let signer = &[&pool_signer_seeds[..]];
+        transfer_checked(transfer_in_cpi, amount_in, ctx.accounts.source_mint.decimals)?;
 
+        // 2. Each hop pays its output straight into the next hop's input vault,
+        // and the last hop pays the user.
+        for index in 0..hop_count {
+            let (to, mint, decimals) = if index + 1 < hop_count {
+                let mint = InterfaceAccount::<Mint>::try_from(&intermediate_mints[index])?;
+                (route[index + 1].vault_in.to_account_info(), mint.to_account_info(), mint.decimals)
+            } else {
+                (
+                    ctx.accounts.user_destination_token_account.to_account_info(),
+                    ctx.accounts.destination_mint.to_account_info(),
+                    ctx.accounts.destination_mint.decimals,
+                )
+            };
 
-        let transfer_out_accounts = TransferChecked {
-            from: dest_vault_account.to_account_info(), // Pool's vault sending the token
-            mint: ctx.accounts.destination_mint.to_account_info(), // Mint of the token being sent out
-            to: ctx.accounts.user_destination_token_account.to_account_info(), // User's destination ATA
-            authority: ctx.accounts.pool_authority.to_account_info(), // The pool's PDA authority
-        };
-        // Create CPI context *with signer* because the authority is a PDA
-        let transfer_out_cpi = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(), // Target program (Token Program)
-            transfer_out_accounts, // Accounts required by transfer_checked
-            signer, // Pass the &[&[&[u8]]] signer seeds
-        );
-        // Execute the CPI
-        transfer_checked(transfer_out_cpi, amount_out, ctx.accounts.destination_mint.decimals)?;
+            let hop = &mut route[index];
+            hop.pay_out(to, mint, decimals, &ctx.accounts.token_program)?;
 
-        // --- Emit Event ---
-        // Log the details of the swap event
-        emit!(SwapEvent {
-            pool: ctx.accounts.pool.key(),
-            user: ctx.accounts.user_authority.key(),
-            amount_in,
-            amount_out,
-            source_mint: ctx.accounts.source_mint.key(),
-            destination_mint: ctx.accounts.destination_mint.key()
-        });
+            emit!(SwapEvent {
+                pool: hop.pool.key(),
+                user: ctx.accounts.user_authority.key(),
+                amount_in: hop.amount_in,
+                amount_out: hop.amount_out,
+                source_mint: hop.vault_in.mint,
+                destination_mint: hop.vault_out.mint,
+                fee_amount: hop.fee_amount,
+                price_impact_bps: hop.price_impact_bps,
+            });
+        }
 
-        Ok(()) // Indicate successful execution
+        Ok(())
     }
 
     /// A simple example instruction to transfer tokens between two accounts.
@@ -278,12 +576,61 @@ pub mod web3_for_dummies {
         Ok(()) // Indicate successful execution
     }
 
-    pub fn add_liquidity(ctx: Context<AddLiquidity>, amount_a: u64, amount_b: u64) -> Result<()> {
+    /// Deposits Token A and Token B into the pool and mints LP shares to the depositor.
+    /// Shares are minted in proportion to what the deposit adds relative to the existing reserves;
+    /// a deposit too small to mint a single share is rejected.
+    /// An optional `deadline` (unix timestamp) rejects the deposit if it lands too late.
+    pub fn add_liquidity(ctx: Context<AddLiquidity>, amount_a: u64, amount_b: u64, deadline: Option<i64>) -> Result<()> {
+        // Reject stale transactions before looking at prices
+        check_deadline(deadline)?;
+
         if amount_a == 0 || amount_b == 0 {
             return err!(SwapError::ZeroAmount);
         }
     
+        // --- Update Oracle ---
+        // Record the price the pool held up to now, before this deposit changes the reserves
+        ctx.accounts.oracle.update(&mut ctx.accounts.pool)?;
+
         let pool = &ctx.accounts.pool;
+
+        // --- Get Reserves ---
+        // Read reserves and LP supply *before* the deposit so the new shares
+        // are priced against the pool as it currently stands (excluding protocol fees).
+        let (reserve_a, reserve_b) = pool.trading_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+
+        // Check for proportional deposits if pool already has liquidity
+        if reserve_a > 0 && reserve_b > 0 {
+            let expected_b = (amount_a as u128)
+                .checked_mul(reserve_b as u128)
+                .ok_or(SwapError::CalculationOverflow)?
+                .checked_div(reserve_a as u128)
+                .ok_or(SwapError::CalculationOverflow)?;
+                    
+            // Allow 1% slippage on the ratio
+            let min_expected_b = expected_b.saturating_mul(99).checked_div(100).unwrap_or(0);
+            let max_expected_b = expected_b.saturating_mul(101).checked_div(100).unwrap_or(u128::MAX);
+
+            let amount_b_u128 = amount_b as u128;
+            
+            let too_small = amount_b_u128 < min_expected_b;
+            let too_large = amount_b_u128 > max_expected_b;
+            
+            if too_small || too_large {
+                return err!(SwapError::DisproportionateLiquidity);
+            }
+        }
+
+        // --- LP Share Calculation ---
+        let lp_amount = pool.lp_shares_for_deposit(amount_a, amount_b, reserve_a, reserve_b, lp_supply)?;
+        // A deposit too small for a single share would just be donated to the other LPs
+        if lp_amount == 0 {
+            return err!(SwapError::ZeroLpShares);
+        }
     
         // Transfer token A
         let transfer_a_accounts = TransferChecked {
@@ -310,162 +657,4725 @@ pub mod web3_for_dummies {
             transfer_b_accounts,
         );
         transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
-    
-        // Check for proportional deposits if pool already has liquidity
+
+        // Mint LP shares: Pool LP Mint -> User
+        // The pool authority PDA is the mint authority, so it has to sign.
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        let mint_lp_accounts = MintTo {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.user_lp_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let mint_lp_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            mint_lp_accounts,
+            signer,
+        );
+        mint_to(mint_lp_cpi, lp_amount)?;
+
+        // --- Sync Reserves ---
         ctx.accounts.token_a_vault.reload()?;
         ctx.accounts.token_b_vault.reload()?;
-        let reserve_a = ctx.accounts.token_a_vault.amount;
-        let reserve_b = ctx.accounts.token_b_vault.amount;
-    
-        // Only check proportions if we already have liquidity
-        let too_small: bool;
-        let too_large: bool;
-        
-        if reserve_a > 0 && reserve_b > 0 {
-            let expected_b = (amount_a as u128)
-                .checked_mul(reserve_b as u128)
-                .ok_or(SwapError::CalculationOverflow)?
-                .checked_div(reserve_a as u128)
-                .ok_or(SwapError::CalculationOverflow)?;
-                    
-            // Allow 1% slippage on the ratio
-            let min_expected_b = expected_b.saturating_mul(99).checked_div(100).unwrap_or(0);
-            let max_expected_b = expected_b.saturating_mul(101).checked_div(100).unwrap_or(u128::MAX);
+        ctx.accounts.pool.sync_reserves(ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)?;
 
-            let amount_b_u128 = amount_b as u128;
-            
-            too_small = amount_b_u128 < min_expected_b;
-            too_large = amount_b_u128 > max_expected_b;
-            
-            if too_small || too_large {
-                return err!(SwapError::DisproportionateLiquidity);
-            }
-        }
-    
         emit!(LiquidityAddedEvent {
-            pool: pool.key(),
+            pool: pool_key,
             user: ctx.accounts.user_authority.key(),
             amount_a,
             amount_b,
+            lp_amount,
         });
     
         Ok(())
     }
-}
 
+    /// Deposits a single token into the pool and mints LP shares for it.
+    /// The optimal portion of `amount_in` is swapped on the pool's own curve so that the rest,
+    /// together with the swap output, matches the pool ratio. Since the swap output never
+    /// leaves the pool, the only transfer is `amount_in` from the user.
+    /// Requires the minimum LP shares expected out (slippage protection).
+    pub fn zap_in(ctx: Context<ZapIn>, token_in_mint: Pubkey, amount_in: u64, min_lp_out: u64) -> Result<()> {
+        if amount_in == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
 
-// --- Account Data Structures ---
+        // --- Update Oracle ---
+        // Record the price the pool held up to now, before this deposit changes the reserves
+        ctx.accounts.oracle.update(&mut ctx.accounts.pool)?;
+        // The internal swap pays the same fee a regular swap would
+        ctx.accounts.pool.decay_volatility(Clock::get()?.unix_timestamp);
 
-/// Stores the state of a single liquidity pool.
-#[account]
-#[derive(Default)] // Allows initializing with default values (zeros, null pubkeys)
-pub struct LiquidityPool {
-    /// The mint address of the first token (Token A).
-    pub token_a_mint: Pubkey,
-    /// The mint address of the second token (Token B).
-    pub token_b_mint: Pubkey,
-    /// The address of the pool's vault (ATA) for Token A.
-    pub token_a_vault: Pubkey,
+        let pool = &ctx.accounts.pool;
+
+        // --- Input Validation ---
+        if token_in_mint != pool.token_a_mint && token_in_mint != pool.token_b_mint {
+            return err!(SwapError::InvalidMint);
+        }
+        if ctx.accounts.user_source_token_account.mint != token_in_mint {
+            return err!(SwapError::InvalidMint);
+        }
+        let is_a_to_b = token_in_mint == pool.token_a_mint;
+
+        // --- Get Reserves ---
+        let (reserve_a, reserve_b) = pool.trading_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        let (reserve_in, reserve_out) = if is_a_to_b { (reserve_a, reserve_b) } else { (reserve_b, reserve_a) };
+
+        // A zap needs an existing price to swap against
+        if reserve_in == 0 || reserve_out == 0 || lp_supply == 0 {
+            return err!(SwapError::PoolIsEmpty);
+        }
+
+        // --- Internal Swap ---
+        let swap_amount = pool.zap_swap_amount(amount_in, reserve_in, reserve_out, is_a_to_b)?;
+        let swap_out = pool.swap_output(swap_amount, reserve_in, reserve_out, is_a_to_b)?;
+        if swap_out == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        let price_impact_bps = pool.price_impact_bps(swap_amount, swap_out, reserve_in, reserve_out, is_a_to_b)?;
+        check_price_impact(price_impact_bps, pool.max_price_impact_bps)?;
+        let fee_amount = pool.fee_amount(swap_amount)?;
+        let protocol_fee = pool.protocol_fee(swap_amount)?;
+
+        // --- LP Share Calculation ---
+        // Shares are priced against the reserves as they stand after the internal swap
+        let post_reserve_in = reserve_in
+            .checked_add(swap_amount)
+            .and_then(|value| value.checked_sub(protocol_fee))
+            .ok_or(SwapError::CalculationOverflow)?;
+        let post_reserve_out = reserve_out.checked_sub(swap_out).ok_or(SwapError::CalculationOverflow)?;
+        let deposit_in = amount_in.checked_sub(swap_amount).ok_or(SwapError::CalculationOverflow)?;
+        let deposit_out = swap_out;
+
+        let (amount_a, amount_b, post_reserve_a, post_reserve_b) = if is_a_to_b {
+            (deposit_in, deposit_out, post_reserve_in, post_reserve_out)
+        } else {
+            (deposit_out, deposit_in, post_reserve_out, post_reserve_in)
+        };
+        let lp_amount = pool.lp_shares_for_deposit(amount_a, amount_b, post_reserve_a, post_reserve_b, lp_supply)?;
+
+        if lp_amount == 0 {
+            return err!(SwapError::ZeroLpShares);
+        }
+        // --- Slippage Check ---
+        if lp_amount < min_lp_out {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // 1. Transfer IN: User -> Pool Vault
+        let (vault_in, mint_in) = if is_a_to_b {
+            (&ctx.accounts.token_a_vault, &ctx.accounts.token_a_mint)
+        } else {
+            (&ctx.accounts.token_b_vault, &ctx.accounts.token_b_mint)
+        };
+        let transfer_in_accounts = TransferChecked {
+            from: ctx.accounts.user_source_token_account.to_account_info(),
+            mint: mint_in.to_account_info(),
+            to: vault_in.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let transfer_in_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_in_accounts,
+        );
+        transfer_checked(transfer_in_cpi, amount_in, mint_in.decimals)?;
+
+        // 2. Mint LP shares: Pool LP Mint -> User
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        let mint_lp_accounts = MintTo {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.user_lp_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let mint_lp_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            mint_lp_accounts,
+            signer,
+        );
+        mint_to(mint_lp_cpi, lp_amount)?;
+
+        // --- Accrue Protocol Fee ---
+        let pool = &mut ctx.accounts.pool;
+        if is_a_to_b {
+            pool.protocol_fees_a = pool.protocol_fees_a.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        } else {
+            pool.protocol_fees_b = pool.protocol_fees_b.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        }
+
+        // --- Track Volatility ---
+        // The internal swap moves the price just like a regular swap would
+        pool.record_price_move(reserve_in, reserve_out, post_reserve_in, post_reserve_out)?;
+
+        // --- Sync Reserves ---
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        ctx.accounts.pool.sync_reserves(ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)?;
+        let pool = &ctx.accounts.pool;
+
+        // --- Emit Events ---
+        let (source_mint, destination_mint) = if is_a_to_b {
+            (pool.token_a_mint, pool.token_b_mint)
+        } else {
+            (pool.token_b_mint, pool.token_a_mint)
+        };
+        emit!(SwapEvent {
+            pool: pool.key(),
+            user: ctx.accounts.user_authority.key(),
+            amount_in: swap_amount,
+            amount_out: swap_out,
+            source_mint,
+            destination_mint,
+            fee_amount,
+            price_impact_bps,
+        });
+        emit!(LiquidityAddedEvent {
+            pool: pool.key(),
+            user: ctx.accounts.user_authority.key(),
+            amount_a,
+            amount_b,
+            lp_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Burns LP shares and pays out the caller's pro-rata share of both vaults.
+    /// Requires the amount of LP tokens to burn and the minimum amounts of Token A and
+    /// Token B expected back (slippage protection).
+    pub fn remove_liquidity(
+        ctx: Context<RemoveLiquidity>,
+        lp_amount: u64,
+        min_amount_a: u64,
+        min_amount_b: u64,
+    ) -> Result<()> {
+        if lp_amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        // --- Update Oracle ---
+        // Record the price the pool held up to now, before this withdrawal changes the reserves
+        ctx.accounts.oracle.update(&mut ctx.accounts.pool)?;
+
+        let pool = &ctx.accounts.pool;
+
+        // --- Get Reserves ---
+        // Protocol fees are not part of the LPs' share
+        let (reserve_a, reserve_b) = pool.trading_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+
+        if lp_supply == 0 {
+            return err!(SwapError::PoolIsEmpty);
+        }
+
+        // --- Payout Calculation ---
+        // amount = reserve * lp_amount / lp_supply (rounded down, favoring the pool)
+        let (amount_a, amount_b) = calculate_withdrawal_amounts(lp_amount, reserve_a, reserve_b, lp_supply)?;
+
+        if amount_a == 0 && amount_b == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        // --- Slippage Check ---
+        if amount_a < min_amount_a || amount_b < min_amount_b {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // 1. Burn LP shares: User -> LP Mint
+        let burn_accounts = Burn {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            from: ctx.accounts.user_lp_token_account.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let burn_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            burn_accounts,
+        );
+        burn(burn_cpi, lp_amount)?;
+
+        // 2. Transfer OUT: Pool Vaults -> User
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        let transfer_a_accounts = TransferChecked {
+            from: ctx.accounts.token_a_vault.to_account_info(),
+            mint: ctx.accounts.token_a_mint.to_account_info(),
+            to: ctx.accounts.user_token_a_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_a_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_a_accounts,
+            signer,
+        );
+        transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
+
+        let transfer_b_accounts = TransferChecked {
+            from: ctx.accounts.token_b_vault.to_account_info(),
+            mint: ctx.accounts.token_b_mint.to_account_info(),
+            to: ctx.accounts.user_token_b_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_b_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_b_accounts,
+            signer,
+        );
+        transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
+
+        // --- Sync Reserves ---
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        ctx.accounts.pool.sync_reserves(ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)?;
+
+        emit!(LiquidityRemovedEvent {
+            pool: pool_key,
+            user: ctx.accounts.user_authority.key(),
+            amount_a,
+            amount_b,
+            lp_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Burns LP shares and pays the caller out in a single token.
+    /// The pro-rata share of the unwanted token is swapped back through the pool's curve,
+    /// so it never leaves the pool; the user receives their share of `output_mint` plus the swap output.
+    /// Requires the minimum amount of `output_mint` expected out (slippage protection).
+    pub fn zap_out(ctx: Context<ZapOut>, lp_amount: u64, output_mint: Pubkey, min_amount_out: u64) -> Result<()> {
+        if lp_amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        // --- Update Oracle ---
+        // Record the price the pool held up to now, before this withdrawal changes the reserves
+        ctx.accounts.oracle.update(&mut ctx.accounts.pool)?;
+        // The internal swap pays the same fee a regular swap would
+        ctx.accounts.pool.decay_volatility(Clock::get()?.unix_timestamp);
+
+        let pool = &ctx.accounts.pool;
+
+        // --- Input Validation ---
+        if output_mint != pool.token_a_mint && output_mint != pool.token_b_mint {
+            return err!(SwapError::InvalidMint);
+        }
+        if ctx.accounts.user_destination_token_account.mint != output_mint {
+            return err!(SwapError::InvalidDestinationMint);
+        }
+        let wants_a = output_mint == pool.token_a_mint;
+
+        // --- Get Reserves ---
+        let (reserve_a, reserve_b) = pool.trading_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        if lp_supply == 0 {
+            return err!(SwapError::PoolIsEmpty);
+        }
+
+        // --- Payout Calculation ---
+        let (amount_a, amount_b) = calculate_withdrawal_amounts(lp_amount, reserve_a, reserve_b, lp_supply)?;
+        let remaining_a = reserve_a.checked_sub(amount_a).ok_or(SwapError::CalculationOverflow)?;
+        let remaining_b = reserve_b.checked_sub(amount_b).ok_or(SwapError::CalculationOverflow)?;
+        let (kept_amount, unwanted_amount, reserve_kept, reserve_unwanted) = if wants_a {
+            (amount_a, amount_b, remaining_a, remaining_b)
+        } else {
+            (amount_b, amount_a, remaining_b, remaining_a)
+        };
+
+        // --- Internal Swap ---
+        // Swap the unwanted side back into the pool, priced against the post-withdrawal reserves
+        let (swap_out, fee_amount, protocol_fee, price_impact_bps) = if unwanted_amount > 0 {
+            if reserve_unwanted == 0 || reserve_kept == 0 {
+                return err!(SwapError::PoolIsEmpty);
+            }
+            let swap_out = pool.swap_output(unwanted_amount, reserve_unwanted, reserve_kept, !wants_a)?;
+            let price_impact_bps = pool.price_impact_bps(unwanted_amount, swap_out, reserve_unwanted, reserve_kept, !wants_a)?;
+            check_price_impact(price_impact_bps, pool.max_price_impact_bps)?;
+            (swap_out, pool.fee_amount(unwanted_amount)?, pool.protocol_fee(unwanted_amount)?, price_impact_bps)
+        } else {
+            (0, 0, 0, 0)
+        };
+
+        let amount_out = kept_amount.checked_add(swap_out).ok_or(SwapError::CalculationOverflow)?;
+        if amount_out == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        // --- Slippage Check ---
+        if amount_out < min_amount_out {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // 1. Burn LP shares: User -> LP Mint
+        let burn_accounts = Burn {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            from: ctx.accounts.user_lp_token_account.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let burn_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            burn_accounts,
+        );
+        burn(burn_cpi, lp_amount)?;
+
+        // 2. Transfer OUT: Pool Vault -> User
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        let (vault_out, mint_out) = if wants_a {
+            (&ctx.accounts.token_a_vault, &ctx.accounts.token_a_mint)
+        } else {
+            (&ctx.accounts.token_b_vault, &ctx.accounts.token_b_mint)
+        };
+        let transfer_out_accounts = TransferChecked {
+            from: vault_out.to_account_info(),
+            mint: mint_out.to_account_info(),
+            to: ctx.accounts.user_destination_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_out_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_out_accounts,
+            signer,
+        );
+        transfer_checked(transfer_out_cpi, amount_out, mint_out.decimals)?;
+
+        // --- Accrue Protocol Fee ---
+        // The internal swap's input is the unwanted token
+        let pool = &mut ctx.accounts.pool;
+        if wants_a {
+            pool.protocol_fees_b = pool.protocol_fees_b.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        } else {
+            pool.protocol_fees_a = pool.protocol_fees_a.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        }
+
+        // --- Track Volatility ---
+        // The internal swap moves the price away from the post-withdrawal reserves
+        if unwanted_amount > 0 {
+            let new_reserve_unwanted = reserve_unwanted
+                .checked_add(unwanted_amount)
+                .and_then(|value| value.checked_sub(protocol_fee))
+                .ok_or(SwapError::CalculationOverflow)?;
+            let new_reserve_kept = reserve_kept.checked_sub(swap_out).ok_or(SwapError::CalculationOverflow)?;
+            pool.record_price_move(reserve_unwanted, reserve_kept, new_reserve_unwanted, new_reserve_kept)?;
+        }
+
+        // --- Sync Reserves ---
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        ctx.accounts.pool.sync_reserves(ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)?;
+        let pool = &ctx.accounts.pool;
+
+        // --- Emit Events ---
+        emit!(LiquidityRemovedEvent {
+            pool: pool.key(),
+            user: ctx.accounts.user_authority.key(),
+            amount_a,
+            amount_b,
+            lp_amount,
+        });
+        if unwanted_amount > 0 {
+            let (source_mint, destination_mint) = if wants_a {
+                (pool.token_b_mint, pool.token_a_mint)
+            } else {
+                (pool.token_a_mint, pool.token_b_mint)
+            };
+            emit!(SwapEvent {
+                pool: pool.key(),
+                user: ctx.accounts.user_authority.key(),
+                amount_in: unwanted_amount,
+                amount_out: swap_out,
+                source_mint,
+                destination_mint,
+                fee_amount,
+                price_impact_bps,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Moves the protocol fees accrued by `swap` out of the pool vaults to the config treasury's
+    /// token accounts. Only the config admin can call this.
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let amount_a = pool.protocol_fees_a;
+        let amount_b = pool.protocol_fees_b;
+
+        if amount_a == 0 && amount_b == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        if amount_a > 0 {
+            let transfer_a_accounts = TransferChecked {
+                from: ctx.accounts.token_a_vault.to_account_info(),
+                mint: ctx.accounts.token_a_mint.to_account_info(),
+                to: ctx.accounts.treasury_token_a_account.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_a_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_a_accounts,
+                signer,
+            );
+            transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
+        }
+
+        if amount_b > 0 {
+            let transfer_b_accounts = TransferChecked {
+                from: ctx.accounts.token_b_vault.to_account_info(),
+                mint: ctx.accounts.token_b_mint.to_account_info(),
+                to: ctx.accounts.treasury_token_b_account.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_b_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_b_accounts,
+                signer,
+            );
+            transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.protocol_fees_a = 0;
+        pool.protocol_fees_b = 0;
+
+        emit!(ProtocolFeesCollectedEvent {
+            pool: pool.key(),
+            admin: ctx.accounts.admin.key(),
+            amount_a,
+            amount_b,
+        });
+
+        Ok(())
+    }
+
+    /// Lets the config admin change the largest price impact a single swap on a pool may have.
+    /// Zero disables the check. Pools are created permissionlessly, so their creators get no
+    /// say over this limit; it is a protocol setting like the pause flags.
+    pub fn set_max_price_impact(ctx: Context<SetMaxPriceImpact>, max_price_impact_bps: u16) -> Result<()> {
+        if max_price_impact_bps as u128 > BPS_DENOMINATOR {
+            return err!(SwapError::InvalidPriceImpactLimit);
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let old_max_price_impact_bps = pool.max_price_impact_bps;
+        pool.max_price_impact_bps = max_price_impact_bps;
+
+        emit!(MaxPriceImpactUpdatedEvent {
+            pool: pool.key(),
+            admin: ctx.accounts.admin.key(),
+            old_max_price_impact_bps,
+            new_max_price_impact_bps: max_price_impact_bps,
+        });
+
+        Ok(())
+    }
+
+    /// `set_max_price_impact` for a concentrated pool.
+    pub fn set_concentrated_max_price_impact(
+        ctx: Context<SetConcentratedMaxPriceImpact>,
+        max_price_impact_bps: u16,
+    ) -> Result<()> {
+        if max_price_impact_bps as u128 > BPS_DENOMINATOR {
+            return err!(SwapError::InvalidPriceImpactLimit);
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let old_max_price_impact_bps = pool.max_price_impact_bps;
+        pool.max_price_impact_bps = max_price_impact_bps;
+
+        emit!(MaxPriceImpactUpdatedEvent {
+            pool: pool.key(),
+            admin: ctx.accounts.admin.key(),
+            old_max_price_impact_bps,
+            new_max_price_impact_bps: max_price_impact_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Starts handing the config admin role to `new_admin`.
+    /// The transfer only takes effect once `new_admin` signs `accept_admin`.
+    pub fn propose_admin(ctx: Context<ProposeAdmin>, new_admin: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.pending_admin = Some(new_admin);
+
+        emit!(AdminProposedEvent {
+            admin: config.admin,
+            proposed_admin: new_admin,
+        });
+
+        Ok(())
+    }
+
+    /// Completes an admin transfer started by `propose_admin`. Must be signed by the proposed admin.
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let old_admin = config.admin;
+        config.admin = ctx.accounts.new_admin.key();
+        config.pending_admin = None;
+
+        emit!(AdminTransferredEvent {
+            old_admin,
+            new_admin: config.admin,
+        });
+
+        Ok(())
+    }
+
+    /// Lets the config admin pause or unpause swaps and deposits across every pool.
+    /// Withdrawals stay open while paused.
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.paused = paused;
+
+        emit!(PauseUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            pool: None,
+            paused,
+        });
+
+        Ok(())
+    }
+
+    /// Lets the config admin pause or unpause swaps and deposits on a single pool.
+    /// Withdrawals stay open while paused.
+    pub fn set_pool_paused(ctx: Context<SetPoolPaused>, paused: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.paused = paused;
+
+        emit!(PauseUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            pool: Some(pool.key()),
+            paused,
+        });
+
+        Ok(())
+    }
+
+    /// `set_pool_paused` for a concentrated pool, stopping `swap_concentrated` and
+    /// `increase_liquidity` while leaving `decrease_liquidity` open.
+    pub fn set_concentrated_pool_paused(ctx: Context<SetConcentratedPoolPaused>, paused: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.paused = paused;
+
+        emit!(PauseUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            pool: Some(pool.key()),
+            paused,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the pool's time-weighted average prices over the last `window_seconds`,
+    /// read from its oracle. Prices are in Q64.64 and raw token units (not adjusted for decimals).
+    /// Fails if the oracle's observations don't reach back to the start of the window; see
+    /// `MIN_OBSERVATION_INTERVAL_SECONDS` for how much history an oracle keeps.
+    pub fn get_twap(ctx: Context<GetTwap>, window_seconds: u32) -> Result<TwapPrice> {
+        // Bring the accumulators forward to now without writing them
+        let now = Clock::get()?.unix_timestamp;
+        let cumulative_now = ctx.accounts.pool.cumulative_prices_at(now);
+        let (price_a_x64, price_b_x64) = ctx.accounts.oracle.twap(cumulative_now, now, window_seconds)?;
+
+        Ok(TwapPrice {
+            price_a_x64,
+            price_b_x64,
+            window_seconds,
+        })
+    }
+
+    /// `get_twap` for a concentrated pool. Its oracle is written by `swap_concentrated`,
+    /// the only instruction that moves a concentrated pool's price.
+    pub fn get_concentrated_twap(ctx: Context<GetConcentratedTwap>, window_seconds: u32) -> Result<TwapPrice> {
+        let now = Clock::get()?.unix_timestamp;
+        let cumulative_now = ctx.accounts.pool.cumulative_prices_at(now);
+        let (price_a_x64, price_b_x64) = ctx.accounts.oracle.twap(cumulative_now, now, window_seconds)?;
+
+        Ok(TwapPrice {
+            price_a_x64,
+            price_b_x64,
+            window_seconds,
+        })
+    }
+
+    /// Initializes a concentrated liquidity pool, where each LP picks the price range
+    /// their liquidity is active in. Prices are quoted as Token B per Token A, so the mints
+    /// have to be passed in sorted order. `initial_sqrt_price_x64` is the starting
+    /// sqrt(price) in Q64.64 fixed point, and positions start and end on multiples of `tick_spacing`.
+    /// The swap fee is `fee_bps` of every input amount and has to be one of the config's fee tiers;
+    /// `None` uses the config's default fee. A pair can have one concentrated pool per tier.
+    /// The config's protocol fee share of every swap fee is set aside for the protocol treasury,
+    /// and `max_price_impact_bps` caps the price impact of a single swap (zero disables the cap);
+    /// the config admin can change it later with `set_concentrated_max_price_impact`.
+    pub fn initialize_concentrated_pool(
+        ctx: Context<InitializeConcentratedPool>,
+        tick_spacing: u16,
+        fee_bps: Option<u16>,
+        initial_sqrt_price_x64: u128,
+        max_price_impact_bps: u16,
+    ) -> Result<()> {
+        // Validate the fee the same way initialize_pool does
+        let config = &ctx.accounts.config;
+        let fee_bps = fee_bps.unwrap_or(config.default_fee_bps);
+        if fee_bps as u128 >= BPS_DENOMINATOR {
+            return err!(SwapError::InvalidFee);
+        }
+        if !config.fee_tiers_bps.contains(&fee_bps) {
+            return err!(SwapError::InvalidFeeTier);
+        }
+        if max_price_impact_bps as u128 > BPS_DENOMINATOR {
+            return err!(SwapError::InvalidPriceImpactLimit);
+        }
+        if tick_spacing == 0 || tick_spacing > MAX_TICK_SPACING {
+            return err!(SwapError::InvalidTickRange);
+        }
+        let tick_current = tick_at_sqrt_price(initial_sqrt_price_x64)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.token_a_mint = ctx.accounts.token_a_mint.key();
+        pool.token_b_mint = ctx.accounts.token_b_mint.key();
+        pool.token_a_vault = ctx.accounts.token_a_vault.key();
+        pool.token_b_vault = ctx.accounts.token_b_vault.key();
+        pool.bump = ctx.bumps.pool;
+        // The vaults belong to a separate authority PDA, as they do for constant-function pools
+        pool.authority_bump = ctx.bumps.pool_authority;
+        pool.tick_spacing = tick_spacing;
+        pool.fee_tier_bps = fee_bps;
+        pool.fee_numerator = fee_bps as u64;
+        pool.fee_denominator = BPS_DENOMINATOR as u64;
+        pool.liquidity = 0;
+        pool.sqrt_price_x64 = initial_sqrt_price_x64;
+        pool.tick_current = tick_current;
+        pool.protocol_fee_share_bps = ctx.accounts.config.protocol_fee_share_bps;
+        pool.protocol_fees_a = 0;
+        pool.protocol_fees_b = 0;
+        pool.max_price_impact_bps = max_price_impact_bps;
+        pool.paused = false;
+
+        // Start the oracle's history at pool creation
+        let now = Clock::get()?.unix_timestamp;
+        pool.last_oracle_update = now;
+        let oracle = &mut ctx.accounts.oracle;
+        oracle.pool = pool.key();
+        oracle.record(Observation {
+            timestamp: now,
+            ..Default::default()
+        });
+
+        msg!("Concentrated Pool Initialized!");
+        msg!("Mint A: {}", pool.token_a_mint);
+        msg!("Mint B: {}", pool.token_b_mint);
+        msg!("Fee: {}/{}", pool.fee_numerator, pool.fee_denominator);
+        msg!("Protocol Fee Share (bps): {}", pool.protocol_fee_share_bps);
+        msg!("Tick Spacing: {}", pool.tick_spacing);
+        msg!("Sqrt Price (Q64.64): {} (tick {})", pool.sqrt_price_x64, pool.tick_current);
+
+        // --- Register The Pool ---
+        // Index the pool under both mints so clients can enumerate pools on-chain
+        let pool_key = pool.key();
+        let accounts = &mut *ctx.accounts;
+        let bumps = &ctx.bumps;
+        for (index, index_bump, page, page_bump, mint) in [
+            (
+                &mut accounts.token_a_pool_index, bumps.token_a_pool_index,
+                &mut accounts.token_a_pool_index_page, bumps.token_a_pool_index_page,
+                accounts.token_a_mint.key(),
+            ),
+            (
+                &mut accounts.token_b_pool_index, bumps.token_b_pool_index,
+                &mut accounts.token_b_pool_index_page, bumps.token_b_pool_index_page,
+                accounts.token_b_mint.key(),
+            ),
+        ] {
+            register_pool(index, index_bump, page, page_bump, mint, pool_key)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates the tick array holding the `TICK_ARRAY_SIZE` ticks from `start_tick_index` up.
+    /// Anyone can create one; positions and swaps need the arrays covering the ticks they touch.
+    pub fn initialize_tick_array(ctx: Context<InitializeTickArray>, start_tick_index: i32) -> Result<()> {
+        let tick_spacing = ctx.accounts.pool.tick_spacing;
+        let ticks_per_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+        if start_tick_index != TickArray::start_index_for(start_tick_index, tick_spacing)
+            || start_tick_index + ticks_per_array <= MIN_TICK
+            || start_tick_index > MAX_TICK
+        {
+            return err!(SwapError::InvalidTickArray);
+        }
+
+        let tick_array = &mut ctx.accounts.tick_array;
+        tick_array.pool = ctx.accounts.pool.key();
+        tick_array.start_tick_index = start_tick_index;
+        Ok(())
+    }
+
+    /// Opens an empty position over the price range [tick_lower, tick_upper) of a concentrated pool.
+    /// Liquidity is added with `increase_liquidity`.
+    pub fn open_position(ctx: Context<OpenPosition>, tick_lower: i32, tick_upper: i32) -> Result<()> {
+        validate_tick_range(tick_lower, tick_upper, ctx.accounts.pool.tick_spacing)?;
+
+        let position = &mut ctx.accounts.position;
+        position.pool = ctx.accounts.pool.key();
+        position.owner = ctx.accounts.owner.key();
+        position.tick_lower = tick_lower;
+        position.tick_upper = tick_upper;
+        position.bump = ctx.bumps.position;
+
+        msg!("Position opened: ticks [{}, {})", tick_lower, tick_upper);
+        Ok(())
+    }
+
+    /// Adds `liquidity` to a position. The Token A and B it takes depend on where the current
+    /// price sits relative to the range, and are capped by `max_amount_a` / `max_amount_b`.
+    pub fn increase_liquidity(
+        ctx: Context<ModifyLiquidity>,
+        liquidity: u128,
+        max_amount_a: u64,
+        max_amount_b: u64,
+    ) -> Result<()> {
+        if liquidity == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        if ctx.accounts.config.paused || ctx.accounts.pool.paused {
+            return err!(SwapError::Paused);
+        }
+        let liquidity_delta = i128::try_from(liquidity).map_err(|_| error!(SwapError::CalculationOverflow))?;
+        let (amount_a, amount_b) = ctx.accounts.modify_position(liquidity_delta)?;
+
+        // --- Slippage Check ---
+        if amount_a > max_amount_a || amount_b > max_amount_b {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // --- Perform Transfers via CPI ---
+        if amount_a > 0 {
+            let transfer_a_accounts = TransferChecked {
+                from: ctx.accounts.user_token_a_account.to_account_info(),
+                mint: ctx.accounts.token_a_mint.to_account_info(),
+                to: ctx.accounts.token_a_vault.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            };
+            let transfer_a_cpi = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_a_accounts,
+            );
+            transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
+        }
+        if amount_b > 0 {
+            let transfer_b_accounts = TransferChecked {
+                from: ctx.accounts.user_token_b_account.to_account_info(),
+                mint: ctx.accounts.token_b_mint.to_account_info(),
+                to: ctx.accounts.token_b_vault.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            };
+            let transfer_b_cpi = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_b_accounts,
+            );
+            transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
+        }
+
+        emit!(PositionLiquidityChangedEvent {
+            pool: ctx.accounts.pool.key(),
+            position: ctx.accounts.position.key(),
+            owner: ctx.accounts.owner.key(),
+            liquidity_delta,
+            amount_a,
+            amount_b,
+            fees_a: 0,
+            fees_b: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Removes `liquidity` from a position and pays out its tokens together with every fee
+    /// the position has earned. Passing zero liquidity only collects the fees.
+    pub fn decrease_liquidity(
+        ctx: Context<ModifyLiquidity>,
+        liquidity: u128,
+        min_amount_a: u64,
+        min_amount_b: u64,
+    ) -> Result<()> {
+        if liquidity > ctx.accounts.position.liquidity {
+            return err!(SwapError::InsufficientLiquidity);
+        }
+        let liquidity_delta = -i128::try_from(liquidity).map_err(|_| error!(SwapError::CalculationOverflow))?;
+        let (amount_a, amount_b) = ctx.accounts.modify_position(liquidity_delta)?;
+
+        // --- Slippage Check ---
+        if amount_a < min_amount_a || amount_b < min_amount_b {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // Fees settled by `modify_position` are paid out with the principal
+        let position = &mut ctx.accounts.position;
+        let (fees_a, fees_b) = (position.fees_owed_a, position.fees_owed_b);
+        position.fees_owed_a = 0;
+        position.fees_owed_b = 0;
+        let total_a = amount_a.checked_add(fees_a).ok_or(SwapError::CalculationOverflow)?;
+        let total_b = amount_b.checked_add(fees_b).ok_or(SwapError::CalculationOverflow)?;
+
+        // --- Perform Transfers via CPI ---
+        // The pool authority PDA owns the vaults, so it signs.
+        let pool = &ctx.accounts.pool;
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        if total_a > 0 {
+            let transfer_a_accounts = TransferChecked {
+                from: ctx.accounts.token_a_vault.to_account_info(),
+                mint: ctx.accounts.token_a_mint.to_account_info(),
+                to: ctx.accounts.user_token_a_account.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_a_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_a_accounts,
+                signer,
+            );
+            transfer_checked(transfer_a_cpi, total_a, ctx.accounts.token_a_mint.decimals)?;
+        }
+        if total_b > 0 {
+            let transfer_b_accounts = TransferChecked {
+                from: ctx.accounts.token_b_vault.to_account_info(),
+                mint: ctx.accounts.token_b_mint.to_account_info(),
+                to: ctx.accounts.user_token_b_account.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_b_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_b_accounts,
+                signer,
+            );
+            transfer_checked(transfer_b_cpi, total_b, ctx.accounts.token_b_mint.decimals)?;
+        }
+
+        emit!(PositionLiquidityChangedEvent {
+            pool: ctx.accounts.pool.key(),
+            position: ctx.accounts.position.key(),
+            owner: ctx.accounts.owner.key(),
+            liquidity_delta,
+            amount_a,
+            amount_b,
+            fees_a,
+            fees_b,
+        });
+
+        Ok(())
+    }
+
+    /// Swaps on a concentrated pool. The price moves through ranges of constant liquidity,
+    /// and every initialized tick it crosses adds or removes the liquidity of the positions
+    /// starting or ending there. `remaining_accounts` holds the tick arrays the swap may walk
+    /// through, in the direction the price moves, starting with the one holding the current tick.
+    /// An optional `deadline` (unix timestamp) rejects the swap if it lands too late.
+    pub fn swap_concentrated(
+        ctx: Context<SwapConcentrated>,
+        amount_in: u64,
+        min_amount_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        // Reject stale transactions before looking at prices
+        check_deadline(deadline)?;
+
+        if amount_in == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        let tick_arrays = ctx.remaining_accounts;
+        if tick_arrays.is_empty() {
+            return err!(SwapError::InvalidTickArray);
+        }
+        // --- Update Oracle ---
+        // Record the price the pool held up to now, before this swap moves it
+        ctx.accounts.oracle.update_concentrated(&mut ctx.accounts.pool)?;
+
+        let pool_key = ctx.accounts.pool.key();
+        let pool = &mut ctx.accounts.pool;
+        // Token A in pushes the price (B per A) down, Token B in pushes it up
+        let a_to_b = ctx.accounts.source_mint.key() == pool.token_a_mint;
+        let tick_spacing = pool.tick_spacing;
+        let ticks_per_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+
+        // --- Walk The Price ---
+        let mut amount_remaining = amount_in as u128;
+        let mut amount_out: u128 = 0;
+        let mut fee_amount: u128 = 0;
+        let mut protocol_fee: u128 = 0;
+        let mut sqrt_price_x64 = pool.sqrt_price_x64;
+        let mut tick_current = pool.tick_current;
+        let mut liquidity = pool.liquidity;
+        let mut fee_growth_global_in = if a_to_b { pool.fee_growth_global_a_x64 } else { pool.fee_growth_global_b_x64 };
+
+        let mut array_index = 0;
+        let mut expected_start = TickArray::start_index_for(tick_current, tick_spacing);
+        let mut tick_array = TickArray::load(&tick_arrays[array_index], pool_key)?;
+        if tick_array.start_tick_index != expected_start {
+            return err!(SwapError::InvalidTickArray);
+        }
+
+        while amount_remaining > 0 {
+            // Head for the next initialized tick, or the edge of the array if there is none
+            let (next_tick, initialized) = match tick_array.next_initialized_tick(tick_current, tick_spacing, a_to_b) {
+                Some(tick) => (tick, true),
+                None if a_to_b => (tick_array.start_tick_index, false),
+                None => (tick_array.start_tick_index + ticks_per_array, false),
+            };
+            let target_tick = next_tick.clamp(MIN_TICK, MAX_TICK);
+            let target_sqrt_price_x64 = sqrt_price_at_tick(target_tick)?;
+
+            let step = compute_swap_step(
+                sqrt_price_x64,
+                target_sqrt_price_x64,
+                liquidity,
+                amount_remaining,
+                pool.fee_numerator,
+                pool.fee_denominator,
+                a_to_b,
+            )?;
+            amount_remaining = amount_remaining
+                .checked_sub(step.amount_in + step.fee_amount)
+                .ok_or(SwapError::CalculationOverflow)?;
+            amount_out = amount_out.checked_add(step.amount_out).ok_or(SwapError::CalculationOverflow)?;
+            fee_amount += step.fee_amount;
+            // The protocol takes its share of the fee and the liquidity in range shares the rest.
+            // With no liquidity in range nobody could claim the fee, so all of it goes to the protocol.
+            let step_protocol_fee = if liquidity == 0 {
+                step.fee_amount
+            } else {
+                step.fee_amount * pool.protocol_fee_share_bps as u128 / BPS_DENOMINATOR
+            };
+            protocol_fee += step_protocol_fee;
+            if let Some(growth) = ((step.fee_amount - step_protocol_fee) << 64).checked_div(liquidity) {
+                fee_growth_global_in = fee_growth_global_in.wrapping_add(growth);
+            }
+            sqrt_price_x64 = step.next_sqrt_price_x64;
+
+            if sqrt_price_x64 != target_sqrt_price_x64 {
+                // The input ran out inside this range
+                tick_current = tick_at_sqrt_price(sqrt_price_x64)?;
+                continue;
+            }
+
+            if initialized {
+                let (global_a, global_b) = if a_to_b {
+                    (fee_growth_global_in, pool.fee_growth_global_b_x64)
+                } else {
+                    (pool.fee_growth_global_a_x64, fee_growth_global_in)
+                };
+                let liquidity_net = tick_array.tick_mut(target_tick, tick_spacing)?.cross(global_a, global_b, a_to_b);
+                liquidity = add_liquidity_delta(liquidity, liquidity_net)?;
+                tick_current = if a_to_b { target_tick - 1 } else { target_tick };
+            } else {
+                // Reached the edge of this array without crossing anything. Going up, the edge is
+                // the next array's first tick, which is crossed (if initialized) from that array.
+                tick_current = target_tick - 1;
+                if amount_remaining > 0 {
+                    if target_tick == MIN_TICK || target_tick == MAX_TICK {
+                        return err!(SwapError::InsufficientLiquidity);
+                    }
+                    tick_array.store(&tick_arrays[array_index])?;
+                    array_index += 1;
+                    if array_index >= tick_arrays.len() {
+                        return err!(SwapError::InvalidTickArray);
+                    }
+                    expected_start += if a_to_b { -ticks_per_array } else { ticks_per_array };
+                    tick_array = TickArray::load(&tick_arrays[array_index], pool_key)?;
+                    if tick_array.start_tick_index != expected_start {
+                        return err!(SwapError::InvalidTickArray);
+                    }
+                }
+            }
+        }
+        tick_array.store(&tick_arrays[array_index])?;
+
+        // --- Slippage Check ---
+        let amount_out = u64::try_from(amount_out).map_err(|_| error!(SwapError::CalculationOverflow))?;
+        if amount_out < min_amount_out {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // --- Price Impact ---
+        // The pre-trade spot price is sqrt_price^2 Token B per Token A; selling B inverts it
+        let spot_squared = U256::from(pool.sqrt_price_x64) * U256::from(pool.sqrt_price_x64);
+        let q128 = U256::one() << 128;
+        let (spot_numerator, spot_denominator) = if a_to_b { (spot_squared, q128) } else { (q128, spot_squared) };
+        // Every step's fee comes out of amount_in, so the total fits in u64
+        let fee_amount = fee_amount as u64;
+        let price_impact_bps = calculate_price_impact_bps(
+            spot_numerator,
+            spot_denominator,
+            amount_in - fee_amount,
+            amount_out,
+            ctx.accounts.source_mint.decimals,
+            ctx.accounts.destination_mint.decimals,
+        )?;
+        check_price_impact(price_impact_bps, pool.max_price_impact_bps)?;
+
+        pool.sqrt_price_x64 = sqrt_price_x64;
+        pool.tick_current = tick_current;
+        pool.liquidity = liquidity;
+        // The protocol fee is part of the fee, so it fits in u64 as well
+        let protocol_fee = protocol_fee as u64;
+        if a_to_b {
+            pool.fee_growth_global_a_x64 = fee_growth_global_in;
+            pool.protocol_fees_a = pool.protocol_fees_a.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        } else {
+            pool.fee_growth_global_b_x64 = fee_growth_global_in;
+            pool.protocol_fees_b = pool.protocol_fees_b.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        }
+
+        // --- Perform Transfers via CPI ---
+        let (vault_in, vault_out) = if a_to_b {
+            (&ctx.accounts.token_a_vault, &ctx.accounts.token_b_vault)
+        } else {
+            (&ctx.accounts.token_b_vault, &ctx.accounts.token_a_vault)
+        };
+
+        // 1. Transfer IN: User -> Pool Vault
+        let transfer_in_accounts = TransferChecked {
+            from: ctx.accounts.user_source_token_account.to_account_info(),
+            mint: ctx.accounts.source_mint.to_account_info(),
+            to: vault_in.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let transfer_in_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_in_accounts,
+        );
+        transfer_checked(transfer_in_cpi, amount_in, ctx.accounts.source_mint.decimals)?;
+
+        // 2. Transfer OUT: Pool Vault -> User, signed by the pool authority PDA
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+        let transfer_out_accounts = TransferChecked {
+            from: vault_out.to_account_info(),
+            mint: ctx.accounts.destination_mint.to_account_info(),
+            to: ctx.accounts.user_destination_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_out_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_out_accounts,
+            signer,
+        );
+        transfer_checked(transfer_out_cpi, amount_out, ctx.accounts.destination_mint.decimals)?;
+
+        emit!(SwapEvent {
+            pool: pool_key,
+            user: ctx.accounts.user_authority.key(),
+            amount_in,
+            amount_out,
+            source_mint: ctx.accounts.source_mint.key(),
+            destination_mint: ctx.accounts.destination_mint.key(),
+            fee_amount,
+            price_impact_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Moves the protocol fees accrued by `swap_concentrated` out of a concentrated pool's vaults
+    /// to the config treasury's token accounts. Only the config admin can call this.
+    pub fn collect_concentrated_protocol_fees(ctx: Context<CollectConcentratedProtocolFees>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let amount_a = pool.protocol_fees_a;
+        let amount_b = pool.protocol_fees_b;
+
+        if amount_a == 0 && amount_b == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        // The pool authority PDA owns the vaults, so it signs.
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        if amount_a > 0 {
+            let transfer_a_accounts = TransferChecked {
+                from: ctx.accounts.token_a_vault.to_account_info(),
+                mint: ctx.accounts.token_a_mint.to_account_info(),
+                to: ctx.accounts.treasury_token_a_account.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_a_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_a_accounts,
+                signer,
+            );
+            transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
+        }
+
+        if amount_b > 0 {
+            let transfer_b_accounts = TransferChecked {
+                from: ctx.accounts.token_b_vault.to_account_info(),
+                mint: ctx.accounts.token_b_mint.to_account_info(),
+                to: ctx.accounts.treasury_token_b_account.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_b_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_b_accounts,
+                signer,
+            );
+            transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.protocol_fees_a = 0;
+        pool.protocol_fees_b = 0;
+
+        emit!(ProtocolFeesCollectedEvent {
+            pool: pool.key(),
+            admin: ctx.accounts.admin.key(),
+            amount_a,
+            amount_b,
+        });
+
+        Ok(())
+    }
+}
+
+
+// --- Validation Helpers ---
+
+/// Returns the two mints sorted by address. Pools are keyed and stored in this order,
+/// so a pair maps to the same pool whichever order the caller passes the mints in.
+pub fn canonical_mint_order(mint_x: Pubkey, mint_y: Pubkey) -> (Pubkey, Pubkey) {
+    if mint_x < mint_y {
+        (mint_x, mint_y)
+    } else {
+        (mint_y, mint_x)
+    }
+}
+
+/// Rejects the transaction if the cluster clock is past `deadline` (unix timestamp).
+pub fn check_deadline(deadline: Option<i64>) -> Result<()> {
+    if let Some(deadline) = deadline {
+        if Clock::get()?.unix_timestamp > deadline {
+            return err!(SwapError::Expired);
+        }
+    }
+    Ok(())
+}
+
+
+// --- Math Helpers ---
+
+/// Integer square root (floor) using Newton's method.
+pub fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+/// Calculates how many LP shares a deposit of (amount_a, amount_b) is worth.
+/// The first deposit gets sqrt(amount_a * amount_b); later deposits get the smaller of
+/// their two proportional contributions so depositors can't dilute existing LPs.
+pub fn calculate_lp_shares(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Result<u64> {
+    let shares = if lp_supply == 0 {
+        let product = (amount_a as u128)
+            .checked_mul(amount_b as u128)
+            .ok_or(SwapError::CalculationOverflow)?;
+        integer_sqrt(product)
+    } else {
+        if reserve_a == 0 || reserve_b == 0 {
+            return err!(SwapError::PoolIsEmpty);
+        }
+        let shares_a = (amount_a as u128)
+            .checked_mul(lp_supply as u128)
+            .ok_or(SwapError::CalculationOverflow)?
+            .checked_div(reserve_a as u128)
+            .ok_or(SwapError::CalculationOverflow)?;
+        let shares_b = (amount_b as u128)
+            .checked_mul(lp_supply as u128)
+            .ok_or(SwapError::CalculationOverflow)?
+            .checked_div(reserve_b as u128)
+            .ok_or(SwapError::CalculationOverflow)?;
+        shares_a.min(shares_b)
+    };
+
+    u64::try_from(shares).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+
+/// Calculates the pro-rata amounts of Token A and Token B that `lp_amount` shares redeem for.
+/// Both amounts are rounded down so rounding dust stays in the pool.
+pub fn calculate_withdrawal_amounts(
+    lp_amount: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Result<(u64, u64)> {
+    if lp_amount > lp_supply {
+        return err!(SwapError::InsufficientLiquidity);
+    }
+    let amount_a = (reserve_a as u128)
+        .checked_mul(lp_amount as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(lp_supply as u128)
+        .ok_or(SwapError::CalculationOverflow)?;
+    let amount_b = (reserve_b as u128)
+        .checked_mul(lp_amount as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(lp_supply as u128)
+        .ok_or(SwapError::CalculationOverflow)?;
+
+    // Both values are <= their reserve, so they always fit back into u64
+    Ok((amount_a as u64, amount_b as u64))
+}
+
+/// Calculates the output amount for an exact-in constant product swap.
+/// The fee (`fee_numerator / fee_denominator`) is deducted from `amount_in` first and the
+/// remainder is priced on the curve: amount_out = y * in_after_fee / (x + in_after_fee).
+pub fn calculate_swap_output(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    // Convert amounts to u128 for calculation to prevent intermediate overflows
+    let amount_in_u128 = amount_in as u128;
+    let reserve_in_u128 = reserve_in as u128;
+    let reserve_out_u128 = reserve_out as u128;
+
+    // Take the fee out of the input amount
+    let amount_in_after_fee = amount_in_u128
+        .checked_mul((fee_denominator as u128).checked_sub(fee_numerator as u128).ok_or(SwapError::InvalidFee)?)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(fee_denominator as u128)
+        .ok_or(SwapError::InvalidFee)?;
+
+    // Calculate the new reserve amount for the input token
+    // new_x = x + amount_in_after_fee
+    let new_reserve_in = reserve_in_u128.checked_add(amount_in_after_fee).ok_or(SwapError::CalculationOverflow)?;
+
+    // amount_out = y - k / new_x = y * amount_in_after_fee / new_x
+    // Note: Integer division truncates, favoring the pool slightly.
+    let amount_out_u128 = reserve_out_u128
+        .checked_mul(amount_in_after_fee)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(new_reserve_in)
+        .ok_or(SwapError::CalculationOverflow)?;
+
+    // amount_out < reserve_out, so it always fits back into u64
+    Ok(amount_out_u128 as u64)
+}
+
+
+/// Calculates the input amount an exact-out constant product swap requires, fee included.
+/// Solves amount_out = y * in_after_fee / (x + in_after_fee) for the input and rounds
+/// every step up so the pool never receives less than the curve requires.
+pub fn calculate_swap_input(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    // The pool can't pay out its whole reserve
+    if amount_out >= reserve_out {
+        return err!(SwapError::InsufficientLiquidity);
+    }
+
+    let amount_out_u128 = amount_out as u128;
+    let reserve_in_u128 = reserve_in as u128;
+    let reserve_out_u128 = reserve_out as u128;
+
+    // in_after_fee = ceil(x * amount_out / (y - amount_out))
+    let amount_in_after_fee = reserve_in_u128
+        .checked_mul(amount_out_u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .div_ceil(reserve_out_u128 - amount_out_u128);
+
+    // amount_in = ceil(in_after_fee * fee_denominator / (fee_denominator - fee_numerator))
+    let fee_multiplier = (fee_denominator as u128).checked_sub(fee_numerator as u128).ok_or(SwapError::InvalidFee)?;
+    if fee_multiplier == 0 {
+        return err!(SwapError::InvalidFee);
+    }
+    let amount_in = amount_in_after_fee
+        .checked_mul(fee_denominator as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .div_ceil(fee_multiplier);
+
+    u64::try_from(amount_in).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Rejects swaps whose price impact (see `calculate_price_impact_bps`) is above the pool's limit.
+/// `max_price_impact_bps` of zero disables the check.
+pub fn check_price_impact(price_impact_bps: u64, max_price_impact_bps: u16) -> Result<()> {
+    if max_price_impact_bps != 0 && price_impact_bps > max_price_impact_bps as u64 {
+        msg!("Price impact {} bps exceeds the pool limit of {} bps", price_impact_bps, max_price_impact_bps);
+        return err!(SwapError::ExcessivePriceImpact);
+    }
+    Ok(())
+}
+
+/// Converts a raw price (`numerator / denominator` output base units per input base unit)
+/// to 18-decimal fixed point in whole tokens, so prices of mints with different decimals compare directly.
+pub fn to_fixed_point_price(numerator: U256, denominator: U256, decimals_in: u8, decimals_out: u8) -> Result<U256> {
+    if denominator.is_zero() {
+        return err!(SwapError::PoolIsEmpty);
+    }
+    // Keep at most 128 significant bits so the scaling below can't overflow
+    let shift = numerator.bits().max(denominator.bits()).saturating_sub(128);
+    let (numerator, denominator) = (numerator >> shift, denominator >> shift);
+    if denominator.is_zero() {
+        return err!(SwapError::CalculationOverflow);
+    }
+
+    // whole-token price = raw price * 10^decimals_in / 10^decimals_out
+    let ten = U256::from(10u8);
+    let scale_in = ten.checked_pow(U256::from(decimals_in)).ok_or(SwapError::CalculationOverflow)?;
+    let scale_out = ten.checked_pow(U256::from(decimals_out)).ok_or(SwapError::CalculationOverflow)?;
+    let scaled = numerator
+        .checked_mul(U256::from(FP_ONE))
+        .and_then(|value| value.checked_mul(scale_in))
+        .ok_or(SwapError::CalculationOverflow)?;
+    Ok(scaled / (denominator * scale_out))
+}
+
+/// Calculates the price impact of a trade in basis points: how much worse the execution price
+/// `amount_out / amount_in_after_fee` is than the pre-trade spot price
+/// `spot_numerator / spot_denominator`, i.e. 1 - execution / spot. Both are raw prices
+/// (output base units per input base unit) and are compared in fixed point; an execution
+/// price at or above spot has no impact.
+pub fn calculate_price_impact_bps(
+    spot_numerator: U256,
+    spot_denominator: U256,
+    amount_in_after_fee: u64,
+    amount_out: u64,
+    decimals_in: u8,
+    decimals_out: u8,
+) -> Result<u64> {
+    if amount_in_after_fee == 0 {
+        // Nothing reached the curve
+        return Ok(BPS_DENOMINATOR as u64);
+    }
+    let spot_price = to_fixed_point_price(spot_numerator, spot_denominator, decimals_in, decimals_out)?;
+    let execution_price = to_fixed_point_price(
+        U256::from(amount_out),
+        U256::from(amount_in_after_fee),
+        decimals_in,
+        decimals_out,
+    )?;
+    if spot_price.is_zero() {
+        // The spot price is below the fixed-point resolution, so the impact can't be measured
+        return err!(SwapError::CalculationOverflow);
+    }
+    if execution_price >= spot_price {
+        return Ok(0);
+    }
+    // At most BPS_DENOMINATOR, so it always fits in u64
+    Ok(((spot_price - execution_price) * U256::from(BPS_DENOMINATOR) / spot_price).as_u64())
+}
+
+/// Calculates how far (in basis points) the reserve ratio moved when the reserves went from
+/// `reserve_in / reserve_out` to `new_reserve_in / new_reserve_out`, i.e.
+/// |new_out * in - out * new_in| / (out * new_in). Saturates at `u64::MAX`.
+pub fn calculate_price_move_bps(
+    reserve_in: u64,
+    reserve_out: u64,
+    new_reserve_in: u64,
+    new_reserve_out: u64,
+) -> Result<u64> {
+    let before = U256::from(reserve_out) * U256::from(new_reserve_in);
+    let after = U256::from(new_reserve_out) * U256::from(reserve_in);
+    if before.is_zero() {
+        return err!(SwapError::PoolIsEmpty);
+    }
+    let difference = if after > before { after - before } else { before - after };
+    let move_bps = difference * U256::from(BPS_DENOMINATOR) / before;
+    Ok(if move_bps > U256::from(u64::MAX) { u64::MAX } else { move_bps.as_u64() })
+}
+
+/// Calculates how much of a single-sided `amount_in` a zap should swap so that the remainder
+/// and the swap output are in the pool's post-swap ratio.
+/// With fee multiplier g = G / D this solves g*s^2 + (1 + g)*r*s - r*a = 0 for s:
+/// s = (sqrt((D + G)^2 * r^2 + 4*G*D*r*a) - (D + G)*r) / (2*G)
+pub fn calculate_zap_swap_amount(
+    amount_in: u64,
+    reserve_in: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    let fee_multiplier = (fee_denominator as u128).checked_sub(fee_numerator as u128).ok_or(SwapError::InvalidFee)?;
+    if fee_multiplier == 0 {
+        return err!(SwapError::InvalidFee);
+    }
+    let amount_in_u128 = amount_in as u128;
+    let reserve_in_u128 = reserve_in as u128;
+    let d_plus_g = (fee_denominator as u128).checked_add(fee_multiplier).ok_or(SwapError::CalculationOverflow)?;
+
+    // The discriminant factors as r * ((D + G)^2 * r + 4*G*D*a)
+    let inner = d_plus_g
+        .checked_mul(d_plus_g)
+        .and_then(|value| value.checked_mul(reserve_in_u128))
+        .and_then(|value| {
+            fee_multiplier
+                .checked_mul(fee_denominator as u128)
+                .and_then(|gd| gd.checked_mul(4))
+                .and_then(|gd| gd.checked_mul(amount_in_u128))
+                .and_then(|term| value.checked_add(term))
+        })
+        .ok_or(SwapError::CalculationOverflow)?;
+    // Take the exact root when the product fits, otherwise split it into two roots
+    let root = match inner.checked_mul(reserve_in_u128) {
+        Some(discriminant) => integer_sqrt(discriminant),
+        None => integer_sqrt(inner)
+            .checked_mul(integer_sqrt(reserve_in_u128))
+            .ok_or(SwapError::CalculationOverflow)?,
+    };
+
+    let linear = d_plus_g.checked_mul(reserve_in_u128).ok_or(SwapError::CalculationOverflow)?;
+    let swap_amount = root.saturating_sub(linear) / (2 * fee_multiplier);
+
+    // The swap can never exceed the amount being zapped in
+    Ok((swap_amount as u64).min(amount_in))
+}
+
+/// Calculates the total swap fee charged on `amount_in`.
+/// It matches the fee deducted in `calculate_swap_output`: whatever of `amount_in`
+/// is left after rounding the post-fee amount down.
+pub fn calculate_fee_amount(amount_in: u64, fee_numerator: u64, fee_denominator: u64) -> Result<u64> {
+    let amount_in_u128 = amount_in as u128;
+    let amount_in_after_fee = amount_in_u128
+        .checked_mul((fee_denominator as u128).checked_sub(fee_numerator as u128).ok_or(SwapError::InvalidFee)?)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(fee_denominator as u128)
+        .ok_or(SwapError::InvalidFee)?;
+    // amount_in_after_fee <= amount_in, so the fee always fits back into u64
+    Ok((amount_in_u128 - amount_in_after_fee) as u64)
+}
+
+/// Calculates the protocol's cut of the swap fee charged on `amount_in`.
+/// The total fee matches the one deducted in `calculate_swap_output`, and the protocol
+/// receives `protocol_fee_share_bps / 10000` of it (rounded down, favoring LPs).
+pub fn calculate_protocol_fee(
+    amount_in: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    protocol_fee_share_bps: u16,
+) -> Result<u64> {
+    let total_fee = calculate_fee_amount(amount_in, fee_numerator, fee_denominator)? as u128;
+
+    let protocol_fee = total_fee
+        .checked_mul(protocol_fee_share_bps as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(BPS_DENOMINATOR)
+        .ok_or(SwapError::CalculationOverflow)?;
+
+    // protocol_fee <= amount_in, so it always fits back into u64
+    Ok(protocol_fee as u64)
+}
+
+
+// --- StableSwap Math ---
+
+mod big_uint {
+    // The uint macro expands to code that trips a few style lints
+    #![allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+    use uint::construct_uint;
+
+    construct_uint! {
+        /// 256-bit unsigned integer, wide enough for StableSwap invariant products.
+        pub struct U256(4);
+    }
+}
+pub use big_uint::U256;
+
+/// Number of tokens in a pool, `n` in the StableSwap formulas.
+const STABLE_N_COINS: u64 = 2;
+
+/// Newton iterations allowed when solving the StableSwap invariant.
+const STABLE_MAX_ITERATIONS: usize = 255;
+
+/// Bisection steps allowed when sizing the internal swap of a StableSwap or Weighted zap.
+const ZAP_BISECTION_ITERATIONS: usize = 64;
+
+/// Smallest allowed StableSwap amplification coefficient.
+pub const MIN_AMP: u64 = 1;
+
+/// Largest allowed StableSwap amplification coefficient.
+pub const MAX_AMP: u64 = 1_000_000;
+
+/// Computes the StableSwap invariant D for normalized balances `x` and `y` using Newton's method:
+/// A*n^n*(x + y) + D = A*n^n*D + D^(n+1) / (n^n * x * y), with Ann = amp * n as in Curve's contracts.
+pub fn compute_stable_d(amp: u64, x: U256, y: U256) -> Result<U256> {
+    let sum = x.checked_add(y).ok_or(SwapError::CalculationOverflow)?;
+    if sum.is_zero() {
+        return Ok(U256::zero());
+    }
+    if x.is_zero() || y.is_zero() {
+        return err!(SwapError::PoolIsEmpty);
+    }
+    let n = U256::from(STABLE_N_COINS);
+    let ann = U256::from(amp) * n;
+
+    let mut d = sum;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        // d_p = D^(n+1) / (n^n * x * y)
+        let mut d_p = d;
+        d_p = d_p.checked_mul(d).ok_or(SwapError::CalculationOverflow)? / (x * n);
+        d_p = d_p.checked_mul(d).ok_or(SwapError::CalculationOverflow)? / (y * n);
+
+        let d_prev = d;
+        let numerator = (ann * sum + d_p * n).checked_mul(d).ok_or(SwapError::CalculationOverflow)?;
+        let denominator = (ann - 1) * d + (n + 1) * d_p;
+        d = numerator / denominator;
+
+        let diff = if d > d_prev { d - d_prev } else { d_prev - d };
+        if diff <= U256::one() {
+            return Ok(d);
+        }
+    }
+    err!(SwapError::CalculationOverflow)
+}
+
+/// Solves the StableSwap invariant for the balance of one token, given the other token's
+/// normalized balance `x` and the invariant `d`.
+pub fn compute_stable_y(amp: u64, x: U256, d: U256) -> Result<U256> {
+    if x.is_zero() {
+        return err!(SwapError::PoolIsEmpty);
+    }
+    let n = U256::from(STABLE_N_COINS);
+    let ann = U256::from(amp) * n;
+
+    // c = D^(n+1) / (n^n * x * Ann), b = x + D / Ann
+    let mut c = d.checked_mul(d).ok_or(SwapError::CalculationOverflow)? / (x * n);
+    c = c.checked_mul(d).ok_or(SwapError::CalculationOverflow)? / (ann * n);
+    let b = x + d / ann;
+
+    let mut y = d;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let y_prev = y;
+        // y = (y^2 + c) / (2y + b - D)
+        y = (y.checked_mul(y).ok_or(SwapError::CalculationOverflow)? + c) / (y * 2 + b - d);
+
+        let diff = if y > y_prev { y - y_prev } else { y_prev - y };
+        if diff <= U256::one() {
+            return Ok(y);
+        }
+    }
+    err!(SwapError::CalculationOverflow)
+}
+
+/// Calculates the output amount for an exact-in StableSwap swap, fee included.
+/// Balances are multiplied by `scale_in`/`scale_out` so both tokens share one precision,
+/// and the output is rounded down.
+#[allow(clippy::too_many_arguments)]
+pub fn calculate_stable_swap_output(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    scale_in: u64,
+    scale_out: u64,
+    amp: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator).ok_or(SwapError::InvalidFee)?;
+    let amount_in_after_fee = (amount_in as u128)
+        .checked_mul(fee_multiplier as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(fee_denominator as u128)
+        .ok_or(SwapError::InvalidFee)?;
+
+    let x = U256::from(reserve_in) * U256::from(scale_in);
+    let y = U256::from(reserve_out) * U256::from(scale_out);
+    let d = compute_stable_d(amp, x, y)?;
+
+    let new_x = x + U256::from(amount_in_after_fee) * U256::from(scale_in);
+    let new_y = compute_stable_y(amp, new_x, d)?;
+
+    // Subtract one extra unit so Newton's rounding never favors the user
+    let amount_out_normalized = y.saturating_sub(new_y).saturating_sub(U256::one());
+    let amount_out = amount_out_normalized / U256::from(scale_out);
+    Ok(amount_out.as_u64().min(reserve_out))
+}
+
+/// Calculates the input amount an exact-out StableSwap swap requires, fee included.
+/// Every step rounds up so the pool never receives less than the curve requires.
+#[allow(clippy::too_many_arguments)]
+pub fn calculate_stable_swap_input(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    scale_in: u64,
+    scale_out: u64,
+    amp: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    if amount_out >= reserve_out {
+        return err!(SwapError::InsufficientLiquidity);
+    }
+
+    let x = U256::from(reserve_in) * U256::from(scale_in);
+    let y = U256::from(reserve_out) * U256::from(scale_out);
+    let d = compute_stable_d(amp, x, y)?;
+
+    let new_y = y - U256::from(amount_out) * U256::from(scale_out);
+    // Add one extra unit so Newton's rounding never favors the user
+    let new_x = compute_stable_y(amp, new_y, d)? + U256::one();
+    let amount_in_normalized = new_x.saturating_sub(x);
+
+    let scale_in_u256 = U256::from(scale_in);
+    let amount_in_after_fee = (amount_in_normalized + scale_in_u256 - 1) / scale_in_u256;
+
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator).ok_or(SwapError::InvalidFee)?;
+    if fee_multiplier == 0 {
+        return err!(SwapError::InvalidFee);
+    }
+    let fee_multiplier_u256 = U256::from(fee_multiplier);
+    let amount_in = (amount_in_after_fee * U256::from(fee_denominator) + fee_multiplier_u256 - 1) / fee_multiplier_u256;
+
+    u64::try_from(amount_in).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+
+// --- Weighted Math ---
+
+/// 1.0 in the 18-decimal fixed-point format used by the weighted math.
+const FP_ONE: u128 = 1_000_000_000_000_000_000;
+
+/// ln(2) in 18-decimal fixed point.
+const FP_LN_2: i128 = 693_147_180_559_945_309;
+
+/// Largest exponent `fp_exp` accepts; e^47 * FP_ONE still fits in a u128.
+const FP_MAX_EXP: i128 = 47 * FP_ONE as i128;
+
+/// Below this exponent e^x rounds to zero at 18 decimals.
+const FP_MIN_EXP: i128 = -42 * FP_ONE as i128;
+
+/// Series terms allowed for ln/exp; both converge well before this.
+const FP_MAX_SERIES_TERMS: i128 = 40;
+
+/// Relative error margin (in FP_ONE units) added to every power so rounding never favors the user.
+const FP_POW_MAX_RELATIVE_ERROR: u128 = 10_000; // 1e-14
+
+/// Smallest allowed weight of either token in a Weighted pool (1%).
+pub const MIN_WEIGHT_BPS: u16 = 100;
+
+/// Natural logarithm of a positive fixed-point number.
+pub fn fp_ln(x: u128) -> Result<i128> {
+    if x == 0 {
+        return err!(SwapError::CalculationOverflow);
+    }
+    // Reduce x to m * 2^k with m in [1, 2)
+    let mut m = x;
+    let mut k: i128 = 0;
+    while m >= 2 * FP_ONE {
+        m >>= 1;
+        k += 1;
+    }
+    while m < FP_ONE {
+        m <<= 1;
+        k -= 1;
+    }
+    // ln(m) = 2 * atanh(z) = 2 * (z + z^3/3 + z^5/5 + ...) with z = (m - 1) / (m + 1) <= 1/3
+    let one = FP_ONE as i128;
+    let m = m as i128;
+    let z = (m - one) * one / (m + one);
+    let z_squared = z * z / one;
+    let mut term = z;
+    let mut sum = z;
+    let mut i = 1;
+    while i < FP_MAX_SERIES_TERMS {
+        term = term * z_squared / one;
+        if term == 0 {
+            break;
+        }
+        sum += term / (2 * i + 1);
+        i += 1;
+    }
+    Ok(2 * sum + k * FP_LN_2)
+}
+
+/// e raised to a fixed-point exponent.
+pub fn fp_exp(x: i128) -> Result<u128> {
+    if x > FP_MAX_EXP {
+        return err!(SwapError::CalculationOverflow);
+    }
+    if x < FP_MIN_EXP {
+        return Ok(0);
+    }
+    // Reduce x to k * ln(2) + r with |r| <= ln(2) / 2, so e^x = 2^k * e^r
+    let k = (x + x.signum() * FP_LN_2 / 2) / FP_LN_2;
+    let r = x - k * FP_LN_2;
+    // Taylor series for e^r
+    let one = FP_ONE as i128;
+    let mut term = one;
+    let mut sum = one;
+    let mut i = 1;
+    while i < FP_MAX_SERIES_TERMS {
+        term = term * r / (i * one);
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        i += 1;
+    }
+    let sum = sum as u128;
+    Ok(if k >= 0 { sum << k } else { sum >> -k })
+}
+
+/// Raises a fixed-point `base` to a fixed-point `exponent`, rounded up by the error margin.
+pub fn fp_pow_up(base: u128, exponent: u128) -> Result<u128> {
+    if base == 0 {
+        return Ok(0);
+    }
+    if exponent == 0 {
+        return Ok(FP_ONE);
+    }
+    let ln_base = fp_ln(base)?;
+    // ln(base) * exponent, split so the product cannot overflow an i128
+    let whole = (exponent / FP_ONE) as i128;
+    let fraction = (exponent % FP_ONE) as i128;
+    let product = ln_base
+        .checked_mul(whole)
+        .and_then(|p| p.checked_add(ln_base * fraction / FP_ONE as i128))
+        .ok_or(SwapError::CalculationOverflow)?;
+    let power = fp_exp(product)?;
+    power
+        .checked_add(power / FP_ONE * FP_POW_MAX_RELATIVE_ERROR + (power % FP_ONE) * FP_POW_MAX_RELATIVE_ERROR / FP_ONE + 1)
+        .ok_or(error!(SwapError::CalculationOverflow))
+}
+
+/// Calculates the output of an exact-in swap on a weighted pool (Balancer's outGivenIn):
+/// out = reserve_out * (1 - (reserve_in / (reserve_in + amount_in_after_fee))^(weight_in / weight_out))
+pub fn calculate_weighted_swap_output(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    weight_in_bps: u16,
+    weight_out_bps: u16,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    if reserve_in == 0 || reserve_out == 0 {
+        return err!(SwapError::PoolIsEmpty);
+    }
+    if weight_in_bps == 0 || weight_out_bps == 0 {
+        return err!(SwapError::InvalidCurveParameters);
+    }
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator).ok_or(SwapError::InvalidFee)?;
+    let amount_in_after_fee = (amount_in as u128)
+        .checked_mul(fee_multiplier as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        / (fee_denominator as u128);
+
+    // Round the base up so the output rounds down
+    let new_reserve_in = (reserve_in as u128) + amount_in_after_fee;
+    let base = ((reserve_in as u128) * FP_ONE).div_ceil(new_reserve_in);
+    let exponent = (weight_in_bps as u128) * FP_ONE / (weight_out_bps as u128);
+    let power = fp_pow_up(base, exponent)?.min(FP_ONE);
+
+    let amount_out = (reserve_out as u128) * (FP_ONE - power) / FP_ONE;
+    u64::try_from(amount_out).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Calculates the input an exact-out swap on a weighted pool requires (Balancer's inGivenOut):
+/// in = reserve_in * ((reserve_out / (reserve_out - amount_out))^(weight_out / weight_in) - 1) / (1 - fee)
+pub fn calculate_weighted_swap_input(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    weight_in_bps: u16,
+    weight_out_bps: u16,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    if reserve_in == 0 || reserve_out == 0 {
+        return err!(SwapError::PoolIsEmpty);
+    }
+    if amount_out >= reserve_out {
+        return err!(SwapError::InsufficientLiquidity);
+    }
+    if weight_in_bps == 0 || weight_out_bps == 0 {
+        return err!(SwapError::InvalidCurveParameters);
+    }
+
+    // Round the base up so the input rounds up
+    let base = ((reserve_out as u128) * FP_ONE).div_ceil((reserve_out - amount_out) as u128);
+    let exponent = (weight_out_bps as u128) * FP_ONE / (weight_in_bps as u128);
+    let power = fp_pow_up(base, exponent)?;
+
+    let amount_in_after_fee = (reserve_in as u128)
+        .checked_mul(power - FP_ONE)
+        .ok_or(SwapError::CalculationOverflow)?
+        .div_ceil(FP_ONE);
+
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator).ok_or(SwapError::InvalidFee)?;
+    if fee_multiplier == 0 {
+        return err!(SwapError::InvalidFee);
+    }
+    let amount_in = amount_in_after_fee
+        .checked_mul(fee_denominator as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .div_ceil(fee_multiplier as u128);
+
+    u64::try_from(amount_in).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Computes the weighted-product invariant V = a^weight_a * b^weight_b, used to size the first deposit.
+pub fn compute_weighted_invariant(amount_a: u64, amount_b: u64, weight_a_bps: u16, weight_b_bps: u16) -> Result<u64> {
+    if amount_a == 0 || amount_b == 0 {
+        return err!(SwapError::ZeroAmount);
+    }
+    let bps = BPS_DENOMINATOR as i128;
+    let weighted_ln = fp_ln((amount_a as u128) * FP_ONE)? * (weight_a_bps as i128) / bps
+        + fp_ln((amount_b as u128) * FP_ONE)? * (weight_b_bps as i128) / bps;
+    // Drop the rounding margin's worth of value so the invariant never rounds up
+    let invariant = fp_exp(weighted_ln)? / FP_ONE;
+    u64::try_from(invariant.saturating_sub(1)).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+
+// --- Concentrated Liquidity Math ---
+
+/// Lowest tick a concentrated pool's price can reach; each tick is a 0.01% price step.
+pub const MIN_TICK: i32 = -443_636;
+
+/// Highest tick a concentrated pool's price can reach.
+pub const MAX_TICK: i32 = 443_636;
+
+/// `sqrt_price_at_tick(MIN_TICK)`: sqrt(1.0001^MIN_TICK) in Q64.64 fixed point.
+pub const MIN_SQRT_PRICE_X64: u128 = 4_295_048_017;
+
+/// `sqrt_price_at_tick(MAX_TICK)`: sqrt(1.0001^MAX_TICK) in Q64.64 fixed point.
+pub const MAX_SQRT_PRICE_X64: u128 = 79_226_673_515_401_279_992_447_579_062;
+
+/// Largest tick spacing a concentrated pool can use.
+pub const MAX_TICK_SPACING: u16 = 16_384;
+
+/// 1 / sqrt(1.0001)^(2^i) in Q128.128 for i = 1..20, as in Uniswap v3's TickMath.
+const TICK_RATIO_FACTORS: [u128; 19] = [
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+/// log_sqrt(1.0001)(2) in Q32.32, converts a log2 into a tick.
+const LOG_SQRT_10001_OF_2_X32: i128 = 59_543_866_431_248;
+
+/// Fractional bits computed when taking the log2 of a sqrt price.
+const LOG2_FRACTION_BITS: u32 = 20;
+
+/// Returns sqrt(1.0001^tick) in Q64.64, rounded up.
+pub fn sqrt_price_at_tick(tick: i32) -> Result<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return err!(SwapError::InvalidTickRange);
+    }
+    let abs_tick = tick.unsigned_abs();
+
+    // Multiply together 1 / sqrt(1.0001)^(2^i) for every bit set in the tick
+    let mut ratio = if abs_tick & 0x1 != 0 {
+        U256::from(0xfffcb933bd6fad37aa2d162d1a594001u128)
+    } else {
+        U256::one() << 128
+    };
+    for (bit, factor) in TICK_RATIO_FACTORS.iter().enumerate() {
+        if abs_tick & (0x2 << bit) != 0 {
+            ratio = (ratio * U256::from(*factor)) >> 128;
+        }
+    }
+    // The factors give the price for a negative tick; invert for a positive one
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Q128.128 -> Q64.64, rounding up
+    let remainder = ratio & U256::from(u64::MAX);
+    let sqrt_price = (ratio >> 64) + if remainder.is_zero() { U256::zero() } else { U256::one() };
+    u128::try_from(sqrt_price).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Returns the greatest tick whose sqrt price is at or below `sqrt_price_x64`.
+pub fn tick_at_sqrt_price(sqrt_price_x64: u128) -> Result<i32> {
+    if !(MIN_SQRT_PRICE_X64..=MAX_SQRT_PRICE_X64).contains(&sqrt_price_x64) {
+        return err!(SwapError::InvalidSqrtPrice);
+    }
+
+    // log2 of the Q64.64 value: the integer part comes from the most significant bit,
+    // the fraction from repeatedly squaring the normalized value.
+    let msb = 127 - sqrt_price_x64.leading_zeros() as i128;
+    let mut log2_x32: i128 = (msb - 64) << 32;
+    let mut r = if msb >= 63 { sqrt_price_x64 >> (msb - 63) } else { sqrt_price_x64 << (63 - msb) };
+    let mut bit: i128 = 1 << 31;
+    for _ in 0..LOG2_FRACTION_BITS {
+        r = (r * r) >> 63;
+        let is_r_more_than_two = (r >> 64) as u32;
+        r >>= is_r_more_than_two;
+        log2_x32 += bit * is_r_more_than_two as i128;
+        bit >>= 1;
+    }
+
+    // Convert to a tick estimate, then correct the last step of rounding exactly
+    let estimate = ((log2_x32 * LOG_SQRT_10001_OF_2_X32) >> 64) as i32;
+    let mut tick = estimate.clamp(MIN_TICK, MAX_TICK);
+    while tick < MAX_TICK && sqrt_price_at_tick(tick + 1)? <= sqrt_price_x64 {
+        tick += 1;
+    }
+    while tick > MIN_TICK && sqrt_price_at_tick(tick)? > sqrt_price_x64 {
+        tick -= 1;
+    }
+    Ok(tick)
+}
+
+/// Token A that `liquidity` holds between two sqrt prices: L * (upper - lower) / (upper * lower).
+pub fn token_a_delta(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> Result<u128> {
+    let (lower, upper) = if sqrt_price_0 < sqrt_price_1 { (sqrt_price_0, sqrt_price_1) } else { (sqrt_price_1, sqrt_price_0) };
+    if lower == 0 {
+        return err!(SwapError::InvalidSqrtPrice);
+    }
+    let numerator = (U256::from(liquidity) << 64)
+        .checked_mul(U256::from(upper - lower))
+        .ok_or(SwapError::CalculationOverflow)?;
+    let denominator = U256::from(upper) * U256::from(lower);
+    let amount = if round_up {
+        (numerator + denominator - 1) / denominator
+    } else {
+        numerator / denominator
+    };
+    u128::try_from(amount).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Token B that `liquidity` holds between two sqrt prices: L * (upper - lower).
+pub fn token_b_delta(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> Result<u128> {
+    let (lower, upper) = if sqrt_price_0 < sqrt_price_1 { (sqrt_price_0, sqrt_price_1) } else { (sqrt_price_1, sqrt_price_0) };
+    let product = U256::from(liquidity) * U256::from(upper - lower);
+    let mut amount = product >> 64;
+    if round_up && !(product & U256::from(u64::MAX)).is_zero() {
+        amount = amount + 1;
+    }
+    u128::try_from(amount).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Returns the sqrt price after swapping `amount_in` (fee already taken) into `liquidity`.
+/// Rounds so the pool never gives out more than the input pays for.
+pub fn next_sqrt_price_from_input(sqrt_price_x64: u128, liquidity: u128, amount_in: u128, a_to_b: bool) -> Result<u128> {
+    if amount_in == 0 {
+        return Ok(sqrt_price_x64);
+    }
+    if liquidity == 0 {
+        return err!(SwapError::PoolIsEmpty);
+    }
+    let next = if a_to_b {
+        // Token A in, price down: L * p / (L + amount * p)
+        let liquidity_x64 = U256::from(liquidity) << 64;
+        let numerator = liquidity_x64
+            .checked_mul(U256::from(sqrt_price_x64))
+            .ok_or(SwapError::CalculationOverflow)?;
+        let denominator = liquidity_x64 + U256::from(amount_in) * U256::from(sqrt_price_x64);
+        (numerator + denominator - 1) / denominator
+    } else {
+        // Token B in, price up: p + amount / L
+        U256::from(sqrt_price_x64) + (U256::from(amount_in) << 64) / U256::from(liquidity)
+    };
+    u128::try_from(next).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Adds a signed liquidity change to an unsigned liquidity amount.
+pub fn add_liquidity_delta(liquidity: u128, delta: i128) -> Result<u128> {
+    if delta >= 0 {
+        liquidity.checked_add(delta as u128).ok_or(error!(SwapError::CalculationOverflow))
+    } else {
+        liquidity.checked_sub(delta.unsigned_abs()).ok_or(error!(SwapError::InsufficientLiquidity))
+    }
+}
+
+/// The result of moving the price within a single range of constant liquidity.
+pub struct SwapStep {
+    pub next_sqrt_price_x64: u128,
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub fee_amount: u128,
+}
+
+/// Swaps as much of `amount_remaining` as fits between the current price and `target_sqrt_price_x64`.
+pub fn compute_swap_step(
+    sqrt_price_x64: u128,
+    target_sqrt_price_x64: u128,
+    liquidity: u128,
+    amount_remaining: u128,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    a_to_b: bool,
+) -> Result<SwapStep> {
+    // The target has to lie in the direction the price moves
+    if (a_to_b && target_sqrt_price_x64 > sqrt_price_x64) || (!a_to_b && target_sqrt_price_x64 < sqrt_price_x64) {
+        return err!(SwapError::InvalidSqrtPrice);
+    }
+    let fee_multiplier = fee_denominator.checked_sub(fee_numerator).ok_or(SwapError::InvalidFee)? as u128;
+    let amount_remaining_less_fee = amount_remaining
+        .checked_mul(fee_multiplier)
+        .ok_or(SwapError::CalculationOverflow)?
+        / (fee_denominator as u128);
+
+    // Input needed to push the price all the way to the target
+    let amount_to_target = if a_to_b {
+        token_a_delta(target_sqrt_price_x64, sqrt_price_x64, liquidity, true)?
+    } else {
+        token_b_delta(sqrt_price_x64, target_sqrt_price_x64, liquidity, true)?
+    };
+    let reached_target = amount_remaining_less_fee >= amount_to_target;
+    let next_sqrt_price_x64 = if reached_target {
+        target_sqrt_price_x64
+    } else {
+        next_sqrt_price_from_input(sqrt_price_x64, liquidity, amount_remaining_less_fee, a_to_b)?
+    };
+
+    let amount_in = if reached_target {
+        amount_to_target
+    } else if a_to_b {
+        token_a_delta(next_sqrt_price_x64, sqrt_price_x64, liquidity, true)?
+    } else {
+        token_b_delta(sqrt_price_x64, next_sqrt_price_x64, liquidity, true)?
+    };
+    let amount_out = if a_to_b {
+        token_b_delta(next_sqrt_price_x64, sqrt_price_x64, liquidity, false)?
+    } else {
+        token_a_delta(sqrt_price_x64, next_sqrt_price_x64, liquidity, false)?
+    };
+
+    // Stopping short of the target means the whole remaining input was used, the rest is fee
+    let unused = amount_remaining.checked_sub(amount_in).ok_or(SwapError::CalculationOverflow)?;
+    let fee_amount = if reached_target {
+        (amount_in * fee_numerator as u128).div_ceil(fee_multiplier).min(unused)
+    } else {
+        unused
+    };
+
+    Ok(SwapStep {
+        next_sqrt_price_x64,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+/// Fees earned per unit of liquidity between two ticks, for tokens A and B.
+pub fn fee_growth_inside(
+    tick_lower_index: i32,
+    tick_lower: &Tick,
+    tick_upper_index: i32,
+    tick_upper: &Tick,
+    tick_current: i32,
+    fee_growth_global_a_x64: u128,
+    fee_growth_global_b_x64: u128,
+) -> (u128, u128) {
+    // "Outside" values flip meaning depending on which side of the tick the price is
+    let (below_a, below_b) = if tick_current >= tick_lower_index {
+        (tick_lower.fee_growth_outside_a_x64, tick_lower.fee_growth_outside_b_x64)
+    } else {
+        (
+            fee_growth_global_a_x64.wrapping_sub(tick_lower.fee_growth_outside_a_x64),
+            fee_growth_global_b_x64.wrapping_sub(tick_lower.fee_growth_outside_b_x64),
+        )
+    };
+    let (above_a, above_b) = if tick_current < tick_upper_index {
+        (tick_upper.fee_growth_outside_a_x64, tick_upper.fee_growth_outside_b_x64)
+    } else {
+        (
+            fee_growth_global_a_x64.wrapping_sub(tick_upper.fee_growth_outside_a_x64),
+            fee_growth_global_b_x64.wrapping_sub(tick_upper.fee_growth_outside_b_x64),
+        )
+    };
+    (
+        fee_growth_global_a_x64.wrapping_sub(below_a).wrapping_sub(above_a),
+        fee_growth_global_b_x64.wrapping_sub(below_b).wrapping_sub(above_b),
+    )
+}
+
+/// Checks that a position's ticks are ordered, in range and on the pool's tick spacing.
+pub fn validate_tick_range(tick_lower: i32, tick_upper: i32, tick_spacing: u16) -> Result<()> {
+    let spacing = tick_spacing as i32;
+    if tick_lower >= tick_upper
+        || tick_lower < MIN_TICK
+        || tick_upper > MAX_TICK
+        || tick_lower % spacing != 0
+        || tick_upper % spacing != 0
+    {
+        return err!(SwapError::InvalidTickRange);
+    }
+    Ok(())
+}
+
+
+// --- Account Data Structures ---
+
+/// Program-wide settings, stored in a singleton PDA created by `initialize_config`.
+#[account]
+#[derive(Default)]
+pub struct ProgramConfig {
+    /// The account allowed to manage the program config.
+    pub admin: Pubkey,
+    /// The admin proposed by `propose_admin`, waiting to accept the role.
+    pub pending_admin: Option<Pubkey>,
+    /// The owner of the token accounts protocol fees are collected into.
+    pub treasury: Pubkey,
+    /// Swap fee (in basis points) for pools created without an explicit fee tier.
+    pub default_fee_bps: u16,
+    /// Share of each swap fee (in basis points) that new pools set aside for the protocol.
+    pub protocol_fee_share_bps: u16,
+    /// The swap fees (in basis points) pools are allowed to charge.
+    pub fee_tiers_bps: Vec<u16>,
+    /// Whether swaps and deposits are paused across every pool.
+    pub paused: bool,
+    /// The bump seed used for the config's PDA.
+    pub bump: u8,
+}
+
+/// Maximum number of fee tiers the program config can hold.
+pub const MAX_FEE_TIERS: usize = 8;
+
+/// Define the space required for the ProgramConfig account.
+const PROGRAM_CONFIG_ACCOUNT_SIZE: usize = 8 + ( 32 * 2 ) + ( 1 + 32 ) + ( 2 * 2 ) + ( 4 + 2 * MAX_FEE_TIERS ) + 1 + 1; // = 131 bytes
+
+/// Maximum number of pools a single page of a mint's pool index can hold.
+pub const MINT_POOL_INDEX_PAGE_CAPACITY: u32 = 32;
+
+/// Counts the pools (constant-function and concentrated) that trade a given mint, on either side.
+/// Lives at `[b"mint_pools", mint]`. The pools themselves are listed in fixed-size
+/// `MintPoolIndexPage`s, so registering a pool only ever touches bounded accounts.
+#[account]
+pub struct MintPoolIndex {
+    /// The mint whose pools are counted.
+    pub mint: Pubkey,
+    /// Number of pools registered so far; pool `n` is listed on page `n / MINT_POOL_INDEX_PAGE_CAPACITY`.
+    pub pool_count: u32,
+    /// The bump seed used for the index's PDA.
+    pub bump: u8,
+}
+
+/// Define the space required for the MintPoolIndex account.
+const MINT_POOL_INDEX_ACCOUNT_SIZE: usize = 8 + 32 + 4 + 1; // = 45 bytes
+
+/// One page of a mint's pool index, at `[b"mint_pools", mint, page (u32, little-endian)]`.
+#[account]
+pub struct MintPoolIndexPage {
+    /// The mint whose pools are listed.
+    pub mint: Pubkey,
+    /// This page's number, starting from zero.
+    pub page: u32,
+    /// Up to `MINT_POOL_INDEX_PAGE_CAPACITY` pools trading `mint`, in creation order.
+    pub pools: Vec<Pubkey>,
+    /// The bump seed used for the page's PDA.
+    pub bump: u8,
+}
+
+/// Define the space required for a full MintPoolIndexPage account.
+const MINT_POOL_INDEX_PAGE_ACCOUNT_SIZE: usize = 8 + 32 + 4 + ( 4 + 32 * MINT_POOL_INDEX_PAGE_CAPACITY as usize ) + 1; // = 1073 bytes
+
+/// Appends `pool` to a mint's pool index. `page` has to be the index's current page,
+/// `[b"mint_pools", mint, pool_count / MINT_POOL_INDEX_PAGE_CAPACITY]`, which always has room.
+fn register_pool(
+    index: &mut MintPoolIndex,
+    index_bump: u8,
+    page: &mut MintPoolIndexPage,
+    page_bump: u8,
+    mint: Pubkey,
+    pool: Pubkey,
+) -> Result<()> {
+    // Freshly created accounts start zeroed
+    if index.pool_count == 0 {
+        index.mint = mint;
+        index.bump = index_bump;
+    }
+    if page.pools.is_empty() {
+        page.mint = mint;
+        page.page = index.pool_count / MINT_POOL_INDEX_PAGE_CAPACITY;
+        page.bump = page_bump;
+    }
+
+    page.pools.push(pool);
+    index.pool_count = index.pool_count.checked_add(1).ok_or(SwapError::CalculationOverflow)?;
+    Ok(())
+}
+
+/// Stores the state of a single liquidity pool.
+#[account]
+#[derive(Default)] // Allows initializing with default values (zeros, null pubkeys)
+pub struct LiquidityPool {
+    /// The mint address of the first token (Token A).
+    pub token_a_mint: Pubkey,
+    /// The mint address of the second token (Token B).
+    pub token_b_mint: Pubkey,
+    /// The address of the pool's vault (ATA) for Token A.
+    pub token_a_vault: Pubkey,
     /// The address of the pool's vault (ATA) for Token B.
     pub token_b_vault: Pubkey,
     /// The bump seed used for the pool's PDA.
     pub bump: u8,
+    /// The mint of the pool's LP share tokens.
+    pub lp_mint: Pubkey,
+    /// Numerator of the swap fee charged on every input amount.
+    pub fee_numerator: u64,
+    /// Denominator of the swap fee charged on every input amount.
+    pub fee_denominator: u64,
+    /// Share of each swap fee (in basis points) that goes to the protocol instead of LPs.
+    pub protocol_fee_share_bps: u16,
+    /// Protocol fees accrued in Token A that have not been collected yet.
+    pub protocol_fees_a: u64,
+    /// Protocol fees accrued in Token B that have not been collected yet.
+    pub protocol_fees_b: u64,
+    /// The pricing curve used by swaps and single-sided deposits.
+    pub curve_type: CurveType,
+    /// The StableSwap amplification coefficient (zero for constant product pools).
+    pub amp: u64,
+    /// Decimals of Token A, used to normalize balances for StableSwap math.
+    pub token_a_decimals: u8,
+    /// Decimals of Token B, used to normalize balances for StableSwap math.
+    pub token_b_decimals: u8,
+    /// Weight of Token A in basis points for Weighted pools (zero for other curves).
+    pub token_a_weight_bps: u16,
+    /// Weight of Token B in basis points for Weighted pools (zero for other curves).
+    pub token_b_weight_bps: u16,
+    /// Time-weighted sum of the Token A price (Token B per Token A, Q64.64), wrapping on overflow.
+    pub price_a_cumulative_x64: u128,
+    /// Time-weighted sum of the Token B price (Token A per Token B, Q64.64), wrapping on overflow.
+    pub price_b_cumulative_x64: u128,
+    /// When the cumulative prices were last brought up to date (unix seconds).
+    pub last_oracle_update: i64,
+    /// Whether swaps pay the fixed `fee_numerator / fee_denominator` or a volatility-based fee.
+    pub fee_mode: FeeMode,
+    /// Decaying measure of recent price moves caused by swaps, in basis points.
+    pub volatility_bps: u32,
+    /// When `volatility_bps` was last decayed (unix seconds).
+    pub last_volatility_update: i64,
+    /// Largest price impact (in basis points) a single swap may have; zero disables the check.
+    pub max_price_impact_bps: u16,
+    /// Whether swaps and deposits into this pool are paused.
+    pub paused: bool,
+    /// The fee tier (in basis points) the pool was created with; part of the pool's PDA seeds.
+    pub fee_tier_bps: u16,
+    /// The bump seed for the pool's vault authority PDA, `[b"authority", pool]`.
+    pub authority_bump: u8,
+    /// Token A reserve left by the last swap or liquidity change; the oracle prices from it.
+    pub reserve_a: u64,
+    /// Token B reserve left by the last swap or liquidity change; the oracle prices from it.
+    pub reserve_b: u64,
+}
+
+/// The pricing curve a pool uses.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum CurveType {
+    /// Uniswap-style x * y = k.
+    #[default]
+    ConstantProduct,
+    /// Curve-style StableSwap invariant for pegged assets.
+    StableSwap,
+    /// Balancer-style weighted product a^weight_a * b^weight_b = k.
+    Weighted,
+}
+
+/// How a pool prices its swap fee.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum FeeMode {
+    /// Every swap pays `fee_numerator / fee_denominator`.
+    #[default]
+    Fixed,
+    /// The fee starts at `min_fee_bps` and rises with recent volatility, up to `max_fee_bps`.
+    Dynamic { min_fee_bps: u16, max_fee_bps: u16 },
+}
+
+impl LiquidityPool {
+    /// Returns the signer seeds of the pool's vault authority PDA, `[b"authority", pool, authority_bump]`.
+    /// `pool_key` has to be this pool's address.
+    pub fn authority_signer_seeds<'a>(&'a self, pool_key: &'a Pubkey) -> [&'a [u8]; 3] {
+        [b"authority", pool_key.as_ref(), std::slice::from_ref(&self.authority_bump)]
+    }
+
+    /// Returns the vault balances that belong to LPs, i.e. with uncollected protocol fees removed.
+    pub fn trading_reserves(&self, vault_a_amount: u64, vault_b_amount: u64) -> Result<(u64, u64)> {
+        let reserve_a = vault_a_amount.checked_sub(self.protocol_fees_a).ok_or(SwapError::CalculationOverflow)?;
+        let reserve_b = vault_b_amount.checked_sub(self.protocol_fees_b).ok_or(SwapError::CalculationOverflow)?;
+        Ok((reserve_a, reserve_b))
+    }
+
+    /// Stores the trading reserves an instruction leaves behind, given the vault balances after its transfers.
+    /// Only swaps and liquidity changes call this, so tokens sent straight to a vault never reach the oracle.
+    pub fn sync_reserves(&mut self, vault_a_amount: u64, vault_b_amount: u64) -> Result<()> {
+        let (reserve_a, reserve_b) = self.trading_reserves(vault_a_amount, vault_b_amount)?;
+        self.reserve_a = reserve_a;
+        self.reserve_b = reserve_b;
+        Ok(())
+    }
+
+    /// Returns the cumulative prices brought forward to `now`, assuming the pool held
+    /// its synced reserves since the last update.
+    pub fn cumulative_prices_at(&self, now: i64) -> (u128, u128) {
+        let elapsed = now - self.last_oracle_update;
+        if elapsed <= 0 || self.reserve_a == 0 || self.reserve_b == 0 {
+            return (self.price_a_cumulative_x64, self.price_b_cumulative_x64);
+        }
+        let elapsed = elapsed as u128;
+        let price_a_x64 = ((self.reserve_b as u128) << 64) / self.reserve_a as u128;
+        let price_b_x64 = ((self.reserve_a as u128) << 64) / self.reserve_b as u128;
+        // Like Uniswap's accumulators these wrap; only differences between two readings matter
+        (
+            self.price_a_cumulative_x64.wrapping_add(price_a_x64.wrapping_mul(elapsed)),
+            self.price_b_cumulative_x64.wrapping_add(price_b_x64.wrapping_mul(elapsed)),
+        )
+    }
+
+    /// Returns the swap fee currently charged, as `(numerator, denominator)`.
+    pub fn current_fee(&self) -> (u64, u64) {
+        match self.fee_mode {
+            FeeMode::Fixed => (self.fee_numerator, self.fee_denominator),
+            FeeMode::Dynamic { min_fee_bps, max_fee_bps } => {
+                let surcharge_bps = self.volatility_bps as u64 * VOLATILITY_FEE_SHARE_BPS / BPS_DENOMINATOR as u64;
+                let fee_bps = (min_fee_bps as u64 + surcharge_bps).min(max_fee_bps as u64);
+                (fee_bps, BPS_DENOMINATOR as u64)
+            }
+        }
+    }
+
+    /// Returns the total fee (LP and protocol share) charged on a swap of `amount_in`.
+    pub fn fee_amount(&self, amount_in: u64) -> Result<u64> {
+        let (fee_numerator, fee_denominator) = self.current_fee();
+        calculate_fee_amount(amount_in, fee_numerator, fee_denominator)
+    }
+
+    /// Returns the protocol's cut of the fee charged on a swap of `amount_in`.
+    pub fn protocol_fee(&self, amount_in: u64) -> Result<u64> {
+        let (fee_numerator, fee_denominator) = self.current_fee();
+        calculate_protocol_fee(amount_in, fee_numerator, fee_denominator, self.protocol_fee_share_bps)
+    }
+
+    /// Lets the volatility measure decay for the time since its last update. It halves every
+    /// `VOLATILITY_HALF_LIFE_SECONDS`, interpolated linearly within each half-life.
+    pub fn decay_volatility(&mut self, now: i64) {
+        let elapsed = now - self.last_volatility_update;
+        if elapsed <= 0 {
+            return;
+        }
+        self.last_volatility_update = now;
+        let half_lives = elapsed / VOLATILITY_HALF_LIFE_SECONDS;
+        if half_lives >= u32::BITS as i64 {
+            self.volatility_bps = 0;
+            return;
+        }
+        let halved = (self.volatility_bps >> half_lives) as u64;
+        let remainder = (elapsed % VOLATILITY_HALF_LIFE_SECONDS) as u64;
+        self.volatility_bps = (halved - halved * remainder / (2 * VOLATILITY_HALF_LIFE_SECONDS as u64)) as u32;
+    }
+
+    /// Adds the price move of a swap that took the reserves from `reserve_in / reserve_out`
+    /// to `new_reserve_in / new_reserve_out` to the volatility measure.
+    pub fn record_price_move(
+        &mut self,
+        reserve_in: u64,
+        reserve_out: u64,
+        new_reserve_in: u64,
+        new_reserve_out: u64,
+    ) -> Result<()> {
+        let move_bps = calculate_price_move_bps(reserve_in, reserve_out, new_reserve_in, new_reserve_out)?;
+        self.volatility_bps = (self.volatility_bps as u64)
+            .saturating_add(move_bps)
+            .min(MAX_VOLATILITY_BPS) as u32;
+        Ok(())
+    }
+
+    /// Quotes an exact-in swap on the pool's curve, fee included.
+    pub fn swap_output(&self, amount_in: u64, reserve_in: u64, reserve_out: u64, is_a_to_b: bool) -> Result<u64> {
+        let (fee_numerator, fee_denominator) = self.current_fee();
+        match self.curve_type {
+            CurveType::ConstantProduct => {
+                calculate_swap_output(amount_in, reserve_in, reserve_out, fee_numerator, fee_denominator)
+            }
+            CurveType::StableSwap => {
+                let (scale_in, scale_out) = self.decimal_scales(is_a_to_b)?;
+                calculate_stable_swap_output(
+                    amount_in,
+                    reserve_in,
+                    reserve_out,
+                    scale_in,
+                    scale_out,
+                    self.amp,
+                    fee_numerator,
+                    fee_denominator,
+                )
+            }
+            CurveType::Weighted => {
+                let (weight_in_bps, weight_out_bps) = self.weights(is_a_to_b);
+                calculate_weighted_swap_output(
+                    amount_in,
+                    reserve_in,
+                    reserve_out,
+                    weight_in_bps,
+                    weight_out_bps,
+                    fee_numerator,
+                    fee_denominator,
+                )
+            }
+        }
+    }
+
+    /// Quotes the input an exact-out swap on the pool's curve requires, fee included.
+    pub fn swap_input(&self, amount_out: u64, reserve_in: u64, reserve_out: u64, is_a_to_b: bool) -> Result<u64> {
+        let (fee_numerator, fee_denominator) = self.current_fee();
+        match self.curve_type {
+            CurveType::ConstantProduct => {
+                calculate_swap_input(amount_out, reserve_in, reserve_out, fee_numerator, fee_denominator)
+            }
+            CurveType::StableSwap => {
+                let (scale_in, scale_out) = self.decimal_scales(is_a_to_b)?;
+                calculate_stable_swap_input(
+                    amount_out,
+                    reserve_in,
+                    reserve_out,
+                    scale_in,
+                    scale_out,
+                    self.amp,
+                    fee_numerator,
+                    fee_denominator,
+                )
+            }
+            CurveType::Weighted => {
+                let (weight_in_bps, weight_out_bps) = self.weights(is_a_to_b);
+                calculate_weighted_swap_input(
+                    amount_out,
+                    reserve_in,
+                    reserve_out,
+                    weight_in_bps,
+                    weight_out_bps,
+                    fee_numerator,
+                    fee_denominator,
+                )
+            }
+        }
+    }
+
+    /// Calculates the LP shares for a deposit. Later deposits are always proportional;
+    /// the first deposit is priced by the curve's invariant.
+    pub fn lp_shares_for_deposit(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        lp_supply: u64,
+    ) -> Result<u64> {
+        if lp_supply == 0 && self.curve_type == CurveType::StableSwap {
+            let (scale_a, scale_b) = self.decimal_scales(true)?;
+            let d = compute_stable_d(
+                self.amp,
+                U256::from(amount_a) * U256::from(scale_a),
+                U256::from(amount_b) * U256::from(scale_b),
+            )?;
+            return u64::try_from(d).map_err(|_| error!(SwapError::CalculationOverflow));
+        }
+        if lp_supply == 0 && self.curve_type == CurveType::Weighted {
+            return compute_weighted_invariant(amount_a, amount_b, self.token_a_weight_bps, self.token_b_weight_bps);
+        }
+        calculate_lp_shares(amount_a, amount_b, reserve_a, reserve_b, lp_supply)
+    }
+
+    /// Calculates how much of a single-sided deposit a zap should swap on the pool's curve.
+    pub fn zap_swap_amount(&self, amount_in: u64, reserve_in: u64, reserve_out: u64, is_a_to_b: bool) -> Result<u64> {
+        match self.curve_type {
+            CurveType::ConstantProduct => {
+                let (fee_numerator, fee_denominator) = self.current_fee();
+                calculate_zap_swap_amount(amount_in, reserve_in, fee_numerator, fee_denominator)
+            }
+            CurveType::StableSwap | CurveType::Weighted => {
+                // No closed form: bisect for the split where the leftover input and the
+                // swap output match the post-swap reserve ratio.
+                let mut low = 0u64;
+                let mut high = amount_in;
+                for _ in 0..ZAP_BISECTION_ITERATIONS {
+                    if high - low <= 1 {
+                        break;
+                    }
+                    let mid = low + (high - low) / 2;
+                    let out = self.swap_output(mid, reserve_in, reserve_out, is_a_to_b)?;
+                    // (amount_in - mid) / (reserve_in + mid) vs out / (reserve_out - out)
+                    let leftover_side = ((amount_in - mid) as u128) * ((reserve_out - out) as u128);
+                    let output_side = (out as u128) * ((reserve_in as u128) + (mid as u128));
+                    if leftover_side > output_side {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                Ok(low)
+            }
+        }
+    }
+
+    /// Returns the marginal pre-trade price on the pool's curve as a raw
+    /// `(numerator, denominator)` of output base units per input base unit.
+    pub fn spot_price(&self, reserve_in: u64, reserve_out: u64, is_a_to_b: bool) -> Result<(U256, U256)> {
+        match self.curve_type {
+            CurveType::ConstantProduct => Ok((U256::from(reserve_out), U256::from(reserve_in))),
+            CurveType::StableSwap => {
+                // -dy/dx of the invariant, with d_p = D^3 / (4 * x * y) as in `compute_stable_d`:
+                // (Ann + d_p / x) / (Ann + d_p / y) = (Ann*x*y + d_p*y) / (Ann*x*y + d_p*x)
+                let (scale_in, scale_out) = self.decimal_scales(is_a_to_b)?;
+                let n = U256::from(STABLE_N_COINS);
+                let x = U256::from(reserve_in) * U256::from(scale_in);
+                let y = U256::from(reserve_out) * U256::from(scale_out);
+                let d = compute_stable_d(self.amp, x, y)?;
+                let d_p = d * d / (x * n) * d / (y * n);
+                let ann_xy = U256::from(self.amp) * n * x * y;
+                // Back from normalized to raw units: multiply by scale_in / scale_out
+                Ok((
+                    (ann_xy + d_p * y) * U256::from(scale_in),
+                    (ann_xy + d_p * x) * U256::from(scale_out),
+                ))
+            }
+            CurveType::Weighted => {
+                // (reserve_out / weight_out) / (reserve_in / weight_in)
+                let (weight_in_bps, weight_out_bps) = self.weights(is_a_to_b);
+                Ok((
+                    U256::from(reserve_out) * U256::from(weight_in_bps),
+                    U256::from(reserve_in) * U256::from(weight_out_bps),
+                ))
+            }
+        }
+    }
+
+    /// Calculates the price impact (in basis points) of swapping `amount_in` for `amount_out`
+    /// against the given pre-trade reserves. The fee is left out of the execution price,
+    /// so only the move along the curve counts.
+    pub fn price_impact_bps(
+        &self,
+        amount_in: u64,
+        amount_out: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        is_a_to_b: bool,
+    ) -> Result<u64> {
+        let (decimals_in, decimals_out) = if is_a_to_b {
+            (self.token_a_decimals, self.token_b_decimals)
+        } else {
+            (self.token_b_decimals, self.token_a_decimals)
+        };
+        let (spot_numerator, spot_denominator) = self.spot_price(reserve_in, reserve_out, is_a_to_b)?;
+        let amount_in_after_fee = amount_in - self.fee_amount(amount_in)?;
+        calculate_price_impact_bps(
+            spot_numerator,
+            spot_denominator,
+            amount_in_after_fee,
+            amount_out,
+            decimals_in,
+            decimals_out,
+        )
+    }
+
+    /// Returns the (input, output) token weights in basis points.
+    fn weights(&self, is_a_to_b: bool) -> (u16, u16) {
+        if is_a_to_b {
+            (self.token_a_weight_bps, self.token_b_weight_bps)
+        } else {
+            (self.token_b_weight_bps, self.token_a_weight_bps)
+        }
+    }
+
+    /// Returns the multipliers that bring (input, output) balances to the same precision.
+    fn decimal_scales(&self, is_a_to_b: bool) -> Result<(u64, u64)> {
+        let max_decimals = self.token_a_decimals.max(self.token_b_decimals);
+        let scale_a = 10u64
+            .checked_pow((max_decimals - self.token_a_decimals) as u32)
+            .ok_or(SwapError::CalculationOverflow)?;
+        let scale_b = 10u64
+            .checked_pow((max_decimals - self.token_b_decimals) as u32)
+            .ok_or(SwapError::CalculationOverflow)?;
+        Ok(if is_a_to_b { (scale_a, scale_b) } else { (scale_b, scale_a) })
+    }
+}
+
+/// Define the space required for the LiquidityPool account.
+const POOL_ACCOUNT_SIZE: usize = 8 + ( 32 * 4 ) + 1 + 32 + ( 8 * 2 ) + 2 + ( 8 * 2 ) + 1 + 8 + 2 + 4 + ( 16 * 2 ) + 8 + 5 + 4 + 8 + 2 + 1 + 2 + 1 + ( 8 * 2 ) + 64; // = 361 bytes
+
+/// Denominator for values expressed in basis points.
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Share of the volatility measure (in basis points) added on top of a dynamic fee's minimum,
+/// e.g. a recent 1% move (100 bps) adds 10 bps of fee.
+pub const VOLATILITY_FEE_SHARE_BPS: u64 = 1_000;
+
+/// Seconds it takes the volatility measure to halve when no swaps move the price.
+pub const VOLATILITY_HALF_LIFE_SECONDS: i64 = 300;
+
+/// Cap on the volatility measure, so a single huge move can't pin the fee at its maximum for long.
+pub const MAX_VOLATILITY_BPS: u64 = 100_000;
+
+/// Decimals used for every pool's LP mint.
+pub const LP_MINT_DECIMALS: u8 = 6;
+
+/// LP shares minted to a pool-owned account on the first deposit and never redeemed (as in Uniswap v2).
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+/// Number of observations an oracle's ring buffer holds before overwriting the oldest.
+pub const OBSERVATION_CAPACITY: usize = 64;
+
+/// Minimum time between two observations in an oracle. Swaps in between still accrue the pool's
+/// cumulative prices, they just don't get an observation of their own. However often a pool trades,
+/// its oracle covers at least the last `(OBSERVATION_CAPACITY - 1) * MIN_OBSERVATION_INTERVAL_SECONDS`
+/// seconds (63 minutes) once the buffer has filled up.
+pub const MIN_OBSERVATION_INTERVAL_SECONDS: i64 = 60;
+
+/// A snapshot of a pool's cumulative prices.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug)]
+pub struct Observation {
+    /// When the snapshot was taken (unix seconds).
+    pub timestamp: i64,
+    /// `LiquidityPool::price_a_cumulative_x64` at `timestamp`.
+    pub price_a_cumulative_x64: u128,
+    /// `LiquidityPool::price_b_cumulative_x64` at `timestamp`.
+    pub price_b_cumulative_x64: u128,
+}
+
+impl Observation {
+    /// Linearly interpolates the cumulative prices at `timestamp`, which lies between this
+    /// observation and a `newer` one. The price can change between two observations, so a window
+    /// starting between them is approximated over at most `MIN_OBSERVATION_INTERVAL_SECONDS`.
+    fn cumulative_at(&self, newer: &Observation, timestamp: i64) -> (u128, u128) {
+        let span = U256::from((newer.timestamp - self.timestamp) as u64);
+        let offset = U256::from((timestamp - self.timestamp) as u64);
+        let interpolate = |older: u128, newer: u128| {
+            older.wrapping_add((U256::from(newer.wrapping_sub(older)) * offset / span).low_u128())
+        };
+        (
+            interpolate(self.price_a_cumulative_x64, newer.price_a_cumulative_x64),
+            interpolate(self.price_b_cumulative_x64, newer.price_b_cumulative_x64),
+        )
+    }
+}
+
+/// A pool's TWAP oracle: a ring buffer of timestamped cumulative price observations.
+#[account]
+pub struct PriceOracle {
+    /// The pool whose prices are observed.
+    pub pool: Pubkey,
+    /// Index of the most recent observation.
+    pub observation_index: u16,
+    /// Number of observations written, up to `OBSERVATION_CAPACITY`.
+    pub observation_count: u16,
+    /// The observations, oldest entries overwritten first.
+    pub observations: [Observation; OBSERVATION_CAPACITY],
+}
+
+/// Define the space required for the PriceOracle account.
+const PRICE_ORACLE_ACCOUNT_SIZE: usize = 8 + 32 + 2 + 2 + ( OBSERVATION_CAPACITY * ( 8 + 16 * 2 ) ); // = 2604 bytes
+
+impl PriceOracle {
+    /// Accrues the pool's cumulative prices with the synced reserves it held up to now and records
+    /// an observation. Has to run before an instruction changes the reserves, so a price only
+    /// reaches the oracle if it survives into a later block. Observations are kept at least
+    /// `MIN_OBSERVATION_INTERVAL_SECONDS` apart.
+    pub fn update(&mut self, pool: &mut LiquidityPool) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let (price_a_cumulative_x64, price_b_cumulative_x64) = pool.cumulative_prices_at(now);
+        pool.price_a_cumulative_x64 = price_a_cumulative_x64;
+        pool.price_b_cumulative_x64 = price_b_cumulative_x64;
+        pool.last_oracle_update = pool.last_oracle_update.max(now);
+        self.record(Observation {
+            timestamp: now,
+            price_a_cumulative_x64,
+            price_b_cumulative_x64,
+        });
+        Ok(())
+    }
+
+    /// `update` for a concentrated pool, whose price is its sqrt price rather than a reserve ratio.
+    pub fn update_concentrated(&mut self, pool: &mut ConcentratedPool) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let (price_a_cumulative_x64, price_b_cumulative_x64) = pool.cumulative_prices_at(now);
+        pool.price_a_cumulative_x64 = price_a_cumulative_x64;
+        pool.price_b_cumulative_x64 = price_b_cumulative_x64;
+        pool.last_oracle_update = pool.last_oracle_update.max(now);
+        self.record(Observation {
+            timestamp: now,
+            price_a_cumulative_x64,
+            price_b_cumulative_x64,
+        });
+        Ok(())
+    }
+
+    /// Appends an observation unless the latest one is less than `MIN_OBSERVATION_INTERVAL_SECONDS` old,
+    /// so a burst of swaps can't flush the history out of the ring buffer.
+    pub fn record(&mut self, observation: Observation) {
+        if self.observation_count > 0 {
+            let latest = self.observations[self.observation_index as usize].timestamp;
+            if observation.timestamp - latest < MIN_OBSERVATION_INTERVAL_SECONDS {
+                return;
+            }
+            self.observation_index = ((self.observation_index as usize + 1) % OBSERVATION_CAPACITY) as u16;
+        }
+        self.observations[self.observation_index as usize] = observation;
+        self.observation_count = (self.observation_count + 1).min(OBSERVATION_CAPACITY as u16);
+    }
+
+    /// Returns the average prices over the `window_seconds` before `now`, given the pool's
+    /// cumulative prices at `now`.
+    pub fn twap(&self, cumulative_now: (u128, u128), now: i64, window_seconds: u32) -> Result<(u128, u128)> {
+        if window_seconds == 0 {
+            return err!(SwapError::InvalidTwapWindow);
+        }
+        let target = now - window_seconds as i64;
+
+        // Walk back from the present until an observation at or before the window start
+        let mut newer = Observation {
+            timestamp: now,
+            price_a_cumulative_x64: cumulative_now.0,
+            price_b_cumulative_x64: cumulative_now.1,
+        };
+        for age in 0..self.observation_count as usize {
+            let index = (self.observation_index as usize + OBSERVATION_CAPACITY - age) % OBSERVATION_CAPACITY;
+            let older = self.observations[index];
+            if older.timestamp <= target {
+                let (start_a, start_b) = older.cumulative_at(&newer, target);
+                let window = window_seconds as u128;
+                return Ok((
+                    cumulative_now.0.wrapping_sub(start_a) / window,
+                    cumulative_now.1.wrapping_sub(start_b) / window,
+                ));
+            }
+            newer = older;
+        }
+        err!(SwapError::InsufficientOracleHistory)
+    }
+}
+
+/// The result of `get_twap`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TwapPrice {
+    /// Average Token B per Token A over the window, in Q64.64 and raw token units.
+    pub price_a_x64: u128,
+    /// Average Token A per Token B over the window, in Q64.64 and raw token units.
+    pub price_b_x64: u128,
+    /// The window the averages cover, in seconds.
+    pub window_seconds: u32,
+}
+
+/// Stores the state of a concentrated liquidity pool, where each LP picks the price range
+/// their liquidity is active in. Prices are quoted as Token B per Token A.
+#[account]
+#[derive(Default)]
+pub struct ConcentratedPool {
+    /// The mint address of the first token (Token A), the smaller of the two mint keys.
+    pub token_a_mint: Pubkey,
+    /// The mint address of the second token (Token B).
+    pub token_b_mint: Pubkey,
+    /// The pool's vault (ATA) for Token A, owned by the pool authority PDA.
+    pub token_a_vault: Pubkey,
+    /// The pool's vault (ATA) for Token B, owned by the pool authority PDA.
+    pub token_b_vault: Pubkey,
+    /// The bump seed used for the pool's PDA.
+    pub bump: u8,
+    /// Positions can only start and end on multiples of this many ticks.
+    pub tick_spacing: u16,
+    /// Numerator of the swap fee charged on every input amount.
+    pub fee_numerator: u64,
+    /// Denominator of the swap fee charged on every input amount.
+    pub fee_denominator: u64,
+    /// Liquidity of the positions whose range contains the current price.
+    pub liquidity: u128,
+    /// The current sqrt(price) in Q64.64 fixed point.
+    pub sqrt_price_x64: u128,
+    /// The tick the current price is in.
+    pub tick_current: i32,
+    /// Token A fees earned per unit of liquidity over the pool's lifetime, in Q64.64.
+    pub fee_growth_global_a_x64: u128,
+    /// Token B fees earned per unit of liquidity over the pool's lifetime, in Q64.64.
+    pub fee_growth_global_b_x64: u128,
+    /// Share of each swap fee (in basis points) that goes to the protocol instead of LPs.
+    pub protocol_fee_share_bps: u16,
+    /// Protocol fees accrued in Token A that have not been collected yet.
+    pub protocol_fees_a: u64,
+    /// Protocol fees accrued in Token B that have not been collected yet.
+    pub protocol_fees_b: u64,
+    /// Largest price impact (in basis points) a single swap may have; zero disables the check.
+    pub max_price_impact_bps: u16,
+    /// Time-weighted sum of the Token A price (Token B per Token A, Q64.64), wrapping on overflow.
+    pub price_a_cumulative_x64: u128,
+    /// Time-weighted sum of the Token B price (Token A per Token B, Q64.64), wrapping on overflow.
+    pub price_b_cumulative_x64: u128,
+    /// When the cumulative prices were last brought up to date (unix seconds).
+    pub last_oracle_update: i64,
+    /// Whether swaps and deposits into this pool are paused.
+    pub paused: bool,
+    /// The fee tier (in basis points) the pool was created with; part of the pool's PDA seeds.
+    pub fee_tier_bps: u16,
+    /// The bump seed for the pool's vault authority PDA, `[b"authority", pool]`.
+    pub authority_bump: u8,
+}
+
+/// Define the space required for the ConcentratedPool account.
+const CONCENTRATED_POOL_ACCOUNT_SIZE: usize = 8 + ( 32 * 4 ) + 1 + 2 + ( 8 * 2 ) + ( 16 * 2 ) + 4 + ( 16 * 2 ) + 2 + ( 8 * 2 ) + 2 + ( 16 * 2 ) + 8 + 1 + 2 + 1; // = 287 bytes
+
+impl ConcentratedPool {
+    /// Returns the signer seeds of the pool's vault authority PDA, `[b"authority", pool, authority_bump]`.
+    /// `pool_key` has to be this pool's address.
+    pub fn authority_signer_seeds<'a>(&'a self, pool_key: &'a Pubkey) -> [&'a [u8]; 3] {
+        [b"authority", pool_key.as_ref(), std::slice::from_ref(&self.authority_bump)]
+    }
+
+    /// Returns the cumulative prices brought forward to `now`, assuming the pool held
+    /// its current sqrt price since the last update.
+    pub fn cumulative_prices_at(&self, now: i64) -> (u128, u128) {
+        let elapsed = now - self.last_oracle_update;
+        if elapsed <= 0 || self.sqrt_price_x64 == 0 {
+            return (self.price_a_cumulative_x64, self.price_b_cumulative_x64);
+        }
+        let elapsed = elapsed as u128;
+        // price = sqrt_price^2; near the tick limits one side no longer fits and saturates
+        let price_squared = U256::from(self.sqrt_price_x64) * U256::from(self.sqrt_price_x64);
+        let price_a_x64 = u128::try_from(price_squared >> 64).unwrap_or(u128::MAX);
+        let price_b_x64 = u128::try_from((U256::one() << 192) / price_squared).unwrap_or(u128::MAX);
+        (
+            self.price_a_cumulative_x64.wrapping_add(price_a_x64.wrapping_mul(elapsed)),
+            self.price_b_cumulative_x64.wrapping_add(price_b_x64.wrapping_mul(elapsed)),
+        )
+    }
+}
+
+/// A single initializable tick in a `TickArray`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug)]
+pub struct Tick {
+    /// Whether any position starts or ends at this tick.
+    pub initialized: bool,
+    /// Liquidity added to the pool when the price crosses this tick going up.
+    pub liquidity_net: i128,
+    /// Total liquidity of the positions that reference this tick.
+    pub liquidity_gross: u128,
+    /// Token A fee growth on the other side of this tick from the current price.
+    pub fee_growth_outside_a_x64: u128,
+    /// Token B fee growth on the other side of this tick from the current price.
+    pub fee_growth_outside_b_x64: u128,
+}
+
+impl Tick {
+    /// Applies a position's liquidity change to this tick. Ticks that end up unused are
+    /// cleared by the caller once fees have been settled.
+    pub fn update(
+        &mut self,
+        tick_index: i32,
+        tick_current: i32,
+        liquidity_delta: i128,
+        fee_growth_global_a_x64: u128,
+        fee_growth_global_b_x64: u128,
+        is_upper: bool,
+    ) -> Result<()> {
+        if self.liquidity_gross == 0 {
+            // By convention all fees so far are counted as earned below the current price
+            if tick_index <= tick_current {
+                self.fee_growth_outside_a_x64 = fee_growth_global_a_x64;
+                self.fee_growth_outside_b_x64 = fee_growth_global_b_x64;
+            }
+            self.initialized = true;
+        }
+        self.liquidity_gross = add_liquidity_delta(self.liquidity_gross, liquidity_delta)?;
+        // Lower ticks add liquidity when crossed going up, upper ticks remove it
+        self.liquidity_net = if is_upper {
+            self.liquidity_net.checked_sub(liquidity_delta)
+        } else {
+            self.liquidity_net.checked_add(liquidity_delta)
+        }
+        .ok_or(SwapError::CalculationOverflow)?;
+        Ok(())
+    }
+
+    /// Crosses this tick during a swap, returning the change in active liquidity.
+    pub fn cross(&mut self, fee_growth_global_a_x64: u128, fee_growth_global_b_x64: u128, a_to_b: bool) -> i128 {
+        self.fee_growth_outside_a_x64 = fee_growth_global_a_x64.wrapping_sub(self.fee_growth_outside_a_x64);
+        self.fee_growth_outside_b_x64 = fee_growth_global_b_x64.wrapping_sub(self.fee_growth_outside_b_x64);
+        if a_to_b { -self.liquidity_net } else { self.liquidity_net }
+    }
+}
+
+/// Number of ticks stored in a single `TickArray` account.
+pub const TICK_ARRAY_SIZE: i32 = 32;
+
+/// A run of `TICK_ARRAY_SIZE` consecutive initializable ticks of a concentrated pool.
+#[account]
+pub struct TickArray {
+    /// The concentrated pool these ticks belong to.
+    pub pool: Pubkey,
+    /// The index of the first tick in the array.
+    pub start_tick_index: i32,
+    /// The ticks, `tick_spacing` apart.
+    pub ticks: [Tick; TICK_ARRAY_SIZE as usize],
+}
+
+/// Define the space required for the TickArray account.
+const TICK_ARRAY_ACCOUNT_SIZE: usize = 8 + 32 + 4 + ( TICK_ARRAY_SIZE as usize * ( 1 + 16 * 4 ) ); // = 2124 bytes
+
+impl TickArray {
+    /// Returns the start index of the array that holds `tick`.
+    pub fn start_index_for(tick: i32, tick_spacing: u16) -> i32 {
+        let ticks_per_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+        tick.div_euclid(ticks_per_array) * ticks_per_array
+    }
+
+    /// Loads a tick array passed as an unchecked account, making sure it belongs to `pool`.
+    pub fn load(info: &AccountInfo, pool: Pubkey) -> Result<Box<TickArray>> {
+        if info.owner != &crate::ID || !info.is_writable {
+            return err!(SwapError::InvalidTickArray);
+        }
+        let data = info.try_borrow_data()?;
+        let tick_array = Box::new(TickArray::try_deserialize(&mut &data[..])?);
+        if tick_array.pool != pool {
+            return err!(SwapError::InvalidTickArray);
+        }
+        Ok(tick_array)
+    }
+
+    /// Writes the tick array back into the account it was loaded from.
+    pub fn store(&self, info: &AccountInfo) -> Result<()> {
+        let mut data = info.try_borrow_mut_data()?;
+        let mut writer: &mut [u8] = &mut data;
+        self.try_serialize(&mut writer)
+    }
+
+    /// Returns the tick at `tick_index`, which has to be inside this array and on the tick spacing.
+    pub fn tick_mut(&mut self, tick_index: i32, tick_spacing: u16) -> Result<&mut Tick> {
+        let spacing = tick_spacing as i32;
+        let offset = tick_index - self.start_tick_index;
+        if offset < 0 || offset % spacing != 0 || offset / spacing >= TICK_ARRAY_SIZE {
+            return err!(SwapError::InvalidTickArray);
+        }
+        Ok(&mut self.ticks[(offset / spacing) as usize])
+    }
+
+    /// Finds the next initialized tick a swap would reach in this array: the closest one at or
+    /// below `tick_current` when the price moves down, or above it when the price moves up.
+    pub fn next_initialized_tick(&self, tick_current: i32, tick_spacing: u16, a_to_b: bool) -> Option<i32> {
+        let spacing = tick_spacing as i32;
+        let tick_at = |offset: usize| self.start_tick_index + offset as i32 * spacing;
+        if a_to_b {
+            (0..self.ticks.len())
+                .rev()
+                .find(|&offset| self.ticks[offset].initialized && tick_at(offset) <= tick_current)
+                .map(tick_at)
+        } else {
+            (0..self.ticks.len())
+                .find(|&offset| self.ticks[offset].initialized && tick_at(offset) > tick_current)
+                .map(tick_at)
+        }
+    }
+}
+
+/// A liquidity position in a concentrated pool, active while the price is in [tick_lower, tick_upper).
+#[account]
+#[derive(Default)]
+pub struct Position {
+    /// The concentrated pool this position provides liquidity to.
+    pub pool: Pubkey,
+    /// The account allowed to change the position and receive its tokens.
+    pub owner: Pubkey,
+    /// The lower end of the position's price range.
+    pub tick_lower: i32,
+    /// The upper end of the position's price range.
+    pub tick_upper: i32,
+    /// The liquidity the position provides inside its range.
+    pub liquidity: u128,
+    /// Token A fee growth inside the range when the position was last updated.
+    pub fee_growth_inside_a_last_x64: u128,
+    /// Token B fee growth inside the range when the position was last updated.
+    pub fee_growth_inside_b_last_x64: u128,
+    /// Token A fees earned and not yet paid out.
+    pub fees_owed_a: u64,
+    /// Token B fees earned and not yet paid out.
+    pub fees_owed_b: u64,
+    /// The bump seed used for the position's PDA.
+    pub bump: u8,
+}
+
+/// Define the space required for the Position account.
+const POSITION_ACCOUNT_SIZE: usize = 8 + ( 32 * 2 ) + ( 4 * 2 ) + ( 16 * 3 ) + ( 8 * 2 ) + 1; // = 145 bytes
+
+impl Position {
+    /// Credits the fees earned since the last update and moves the fee checkpoints forward.
+    pub fn settle_fees(&mut self, fee_growth_inside_a_x64: u128, fee_growth_inside_b_x64: u128) -> Result<()> {
+        let earned = |growth: u128, last: u128| -> Result<u64> {
+            let fees = (U256::from(growth.wrapping_sub(last)) * U256::from(self.liquidity)) >> 64;
+            u64::try_from(fees).map_err(|_| error!(SwapError::CalculationOverflow))
+        };
+        let fees_a = earned(fee_growth_inside_a_x64, self.fee_growth_inside_a_last_x64)?;
+        let fees_b = earned(fee_growth_inside_b_x64, self.fee_growth_inside_b_last_x64)?;
+        self.fees_owed_a = self.fees_owed_a.checked_add(fees_a).ok_or(SwapError::CalculationOverflow)?;
+        self.fees_owed_b = self.fees_owed_b.checked_add(fees_b).ok_or(SwapError::CalculationOverflow)?;
+        self.fee_growth_inside_a_last_x64 = fee_growth_inside_a_x64;
+        self.fee_growth_inside_b_last_x64 = fee_growth_inside_b_x64;
+        Ok(())
+    }
+}
+
+/// Defines the accounts required for the `initialize_config` instruction.
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    /// The program-wide config, a singleton PDA.
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"config"],
+        bump,
+        space = PROGRAM_CONFIG_ACCOUNT_SIZE,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// This program, to find the ProgramData account holding its upgrade authority.
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key()) @ SwapError::Unauthorized,
+    )]
+    pub program: Program<'info, crate::program::Web3ForDummies>,
+
+    /// The program's ProgramData account. Only its upgrade authority may create the config,
+    /// so nobody can front-run the deployer and take the admin role.
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ SwapError::Unauthorized,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    /// The program's upgrade authority creating the config (signer, payer), which becomes its admin.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `initialize_pool` instruction.
+/// The pool seeds use the sorted mint pair and the fee tier, so each pair can have exactly one pool per tier.
+#[derive(Accounts)]
+#[instruction(fee_bps: Option<u16>)]
+pub struct InitializePool<'info> {
+    /// The program config, providing the default fee and allowed fee tiers.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The mint account for Token A. Must be passed by the client.
+    /// The mints may come in either order; the pool seeds always use the sorted pair.
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    /// The mint account for Token B. Must be passed by the client.
+    #[account(
+        constraint = token_b_mint.key() != token_a_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// The LiquidityPool account to be created.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"pool",
+            canonical_mint_order(token_a_mint.key(), token_b_mint.key()).0.as_ref(),
+            canonical_mint_order(token_a_mint.key(), token_b_mint.key()).1.as_ref(),
+            fee_bps.unwrap_or(config.default_fee_bps).to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = POOL_ACCOUNT_SIZE,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: The authority PDA for the pool.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The associated token account (vault) for Token A, owned by the pool_authority PDA.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = token_a_mint,
+        associated_token::authority = pool_authority, // Anchor ensures this authority matches the pool_authority account provided
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The associated token account (vault) for Token B, owned by the pool_authority PDA.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = token_b_mint,
+        associated_token::authority = pool_authority, // Anchor ensures this authority matches the pool_authority account provided
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for the pool's LP share tokens, with the pool_authority PDA as mint authority.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"lp_mint",
+            pool.key().as_ref(),
+        ],
+        bump,
+        mint::decimals = LP_MINT_DECIMALS,
+        mint::authority = pool_authority,
+        mint::token_program = token_program,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    /// The pool's TWAP oracle, holding a ring buffer of price observations.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"oracle",
+            pool.key().as_ref(),
+        ],
+        bump,
+        space = PRICE_ORACLE_ACCOUNT_SIZE,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+
+    /// Token A's pool index, created by the first pool that trades Token A.
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        seeds = [
+            b"mint_pools",
+            token_a_mint.key().as_ref(),
+        ],
+        bump,
+        space = MINT_POOL_INDEX_ACCOUNT_SIZE,
+    )]
+    pub token_a_pool_index: Box<Account<'info, MintPoolIndex>>,
+
+    /// The page of Token A's pool index this pool is listed on, created when the previous page fills up.
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        seeds = [
+            b"mint_pools",
+            token_a_mint.key().as_ref(),
+            (token_a_pool_index.pool_count / MINT_POOL_INDEX_PAGE_CAPACITY).to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = MINT_POOL_INDEX_PAGE_ACCOUNT_SIZE,
+    )]
+    pub token_a_pool_index_page: Box<Account<'info, MintPoolIndexPage>>,
+
+    /// Token B's pool index, created by the first pool that trades Token B.
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        seeds = [
+            b"mint_pools",
+            token_b_mint.key().as_ref(),
+        ],
+        bump,
+        space = MINT_POOL_INDEX_ACCOUNT_SIZE,
+    )]
+    pub token_b_pool_index: Box<Account<'info, MintPoolIndex>>,
+
+    /// The page of Token B's pool index this pool is listed on, created when the previous page fills up.
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        seeds = [
+            b"mint_pools",
+            token_b_mint.key().as_ref(),
+            (token_b_pool_index.pool_count / MINT_POOL_INDEX_PAGE_CAPACITY).to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = MINT_POOL_INDEX_PAGE_ACCOUNT_SIZE,
+    )]
+    pub token_b_pool_index_page: Box<Account<'info, MintPoolIndexPage>>,
+
+    /// The initializer's token account for Token A, funding the initial deposit.
+    #[account(
+        mut,
+        token::mint = token_a_mint,
+        token::authority = initializer,
+    )]
+    pub initializer_token_a_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The initializer's token account for Token B, funding the initial deposit.
+    #[account(
+        mut,
+        token::mint = token_b_mint,
+        token::authority = initializer,
+    )]
+    pub initializer_token_b_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The initializer's LP token account, created here since the LP mint is new.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = lp_mint,
+        associated_token::authority = initializer,
+    )]
+    pub initializer_lp_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// LP token account owned by the pool authority, holding the permanently locked `MINIMUM_LIQUIDITY` shares.
+    /// The program never transfers or burns from it.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = lp_mint,
+        associated_token::authority = pool_authority,
+    )]
+    pub locked_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user initializing the pool (signer and payer).
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `swap` instruction.
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    /// The mint account for the token being sent *in*.
+    pub source_mint: InterfaceAccount<'info, Mint>,
+    /// The mint account for the token being sent *out*.
+    pub destination_mint: InterfaceAccount<'info, Mint>,
+
+    /// The LiquidityPool account containing the state for this swap.
+    /// Mutable so the protocol's share of the fee can be accrued.
+    #[account(
+        mut,
+        // We validate the pool implicitly through the pool_authority check and vault constraints.
+        // --- Security Constraints ---
+        // These constraints ensure the provided vaults match the addresses stored in the pool state.
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+        constraint = (pool.token_a_mint == source_mint.key() && pool.token_b_mint == destination_mint.key()) || 
+                    (pool.token_a_mint == destination_mint.key() && pool.token_b_mint == source_mint.key()) 
+                    @ SwapError::InvalidMint,
+        constraint = !pool.paused @ SwapError::Paused,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's TWAP oracle, updated before the reserves change.
+    #[account(
+        mut,
+        has_one = pool @ SwapError::InvalidOracle,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+
+    /// The program config; swaps and deposits stop while it is paused.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ SwapError::Paused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The user's token account for the token they are sending *in*.
+    #[account(
+        mut,
+        constraint = user_source_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_source_token_account.mint == source_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_source_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account for the token they are receiving *out*.
+    #[account(
+        mut,
+        constraint = user_destination_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_destination_token_account.mint == destination_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's vault for Token A. Needs to be mutable for balance changes.
+    #[account(
+        mut,
+        // Constraint to ensure this vault belongs to the validated pool authority PDA.
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+        // Constraint ensuring the vault's mint matches the pool state's mint A.
+        constraint = token_a_vault.mint == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's vault for Token B. Needs to be mutable for balance changes.
+    #[account(
+        mut,
+        // Constraint to ensure this vault belongs to the validated pool authority PDA.
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+        // Constraint ensuring the vault's mint matches the pool state's mint B.
+        constraint = token_b_vault.mint == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user performing the swap (signer).
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> Swap<'info> {
+    /// Returns `true` when the user is sending Token A for Token B, `false` for B to A.
+    fn swap_direction(&self) -> Result<bool> {
+        let pool = &self.pool;
+
+        // Ensure the user's source token account mint matches one of the pool's tokens
+        // This check is partially redundant due to constraints but good for clarity
+        if self.user_source_token_account.mint != pool.token_a_mint && self.user_source_token_account.mint != pool.token_b_mint {
+            return err!(SwapError::InvalidMint);
+        }
+        let is_a_to_b = self.user_source_token_account.mint == pool.token_a_mint;
+
+        // --- Destination Mint Check ---
+        // Ensure the user's destination token account matches the mint of the pool's destination vault
+        // This check is partially redundant due to constraints but good for clarity
+        let dest_vault_mint = if is_a_to_b { self.token_b_vault.mint } else { self.token_a_vault.mint };
+        if self.user_destination_token_account.mint != dest_vault_mint {
+            return err!(SwapError::InvalidDestinationMint);
+        }
+
+        Ok(is_a_to_b)
+    }
+
+    /// Returns `(reserve_in, reserve_out)` for a swap in the given direction.
+    fn trading_reserves(&mut self, is_a_to_b: bool) -> Result<(u64, u64)> {
+        // Reload vault accounts to get the latest balance data on-chain
+        // It's crucial to reload *before* calculations to prevent race conditions.
+        self.token_a_vault.reload()?;
+        self.token_b_vault.reload()?;
+
+        // Accrued protocol fees sit in the vaults but don't belong to LPs,
+        // so they are left out of the reserves used for pricing.
+        let (reserve_a, reserve_b) = self.pool.trading_reserves(self.token_a_vault.amount, self.token_b_vault.amount)?;
+        Ok(if is_a_to_b { (reserve_a, reserve_b) } else { (reserve_b, reserve_a) })
+    }
+
+    /// Moves `amount_in` from the user into the pool and `amount_out` from the pool to the user,
+    /// accrues the protocol fee, records the price move against the pre-swap reserves and
+    /// emits a `SwapEvent`.
+    fn execute_swap(
+        &mut self,
+        is_a_to_b: bool,
+        reserve_in: u64,
+        reserve_out: u64,
+        amount_in: u64,
+        amount_out: u64,
+        price_impact_bps: u64,
+    ) -> Result<()> {
+        // The fee charged and the protocol's cut of it, both paid in the input token
+        let fee_amount = self.pool.fee_amount(amount_in)?;
+        let protocol_fee = self.pool.protocol_fee(amount_in)?;
+
+        // Figure out which pool vault receives tokens (source) and which sends tokens (destination)
+        let (source_vault_account, dest_vault_account) = if is_a_to_b {
+            (&self.token_a_vault, &self.token_b_vault)
+        } else {
+            (&self.token_b_vault, &self.token_a_vault)
+        };
+
+        // 1. Transfer IN: User -> Pool Source Vault
+        let transfer_in_accounts = TransferChecked {
+            from: self.user_source_token_account.to_account_info(), // User's source ATA
+            mint: self.source_mint.to_account_info(), // Mint of the token being sent in
+            to: source_vault_account.to_account_info(), // Pool's vault for receiving the token
+            authority: self.user_authority.to_account_info(), // User signing the transaction
+        };
+        let transfer_in_cpi = CpiContext::new(
+            self.token_program.to_account_info(), // Target program (Token Program)
+            transfer_in_accounts, // Accounts required by transfer_checked
+        );
+        // Execute the CPI
+        transfer_checked(transfer_in_cpi, amount_in, self.source_mint.decimals)?;
+
+
+        // 2. Transfer OUT: Pool Destination Vault -> User
+        // Define the PDA signer seeds for the pool authority
+        let pool = &self.pool;
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        // Add another layer of &[&[u8]] for the signer seeds argument
+        let signer = &[&authority_signer_seeds[..]];
+
+
+        let transfer_out_accounts = TransferChecked {
+            from: dest_vault_account.to_account_info(), // Pool's vault sending the token
+            mint: self.destination_mint.to_account_info(), // Mint of the token being sent out
+            to: self.user_destination_token_account.to_account_info(), // User's destination ATA
+            authority: self.pool_authority.to_account_info(), // The pool's PDA authority
+        };
+        // Create CPI context *with signer* because the authority is a PDA
+        let transfer_out_cpi = CpiContext::new_with_signer(
+            self.token_program.to_account_info(), // Target program (Token Program)
+            transfer_out_accounts, // Accounts required by transfer_checked
+            signer, // Pass the &[&[&[u8]]] signer seeds
+        );
+        // Execute the CPI
+        transfer_checked(transfer_out_cpi, amount_out, self.destination_mint.decimals)?;
+
+        // --- Accrue Protocol Fee ---
+        let pool = &mut self.pool;
+        if is_a_to_b {
+            pool.protocol_fees_a = pool.protocol_fees_a.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        } else {
+            pool.protocol_fees_b = pool.protocol_fees_b.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        }
+
+        // --- Track Volatility ---
+        // The protocol fee leaves the trading reserves, the rest of amount_in stays with LPs
+        let new_reserve_in = reserve_in
+            .checked_add(amount_in)
+            .and_then(|value| value.checked_sub(protocol_fee))
+            .ok_or(SwapError::CalculationOverflow)?;
+        let new_reserve_out = reserve_out.checked_sub(amount_out).ok_or(SwapError::CalculationOverflow)?;
+        pool.record_price_move(reserve_in, reserve_out, new_reserve_in, new_reserve_out)?;
+
+        // --- Sync Reserves ---
+        self.token_a_vault.reload()?;
+        self.token_b_vault.reload()?;
+        self.pool.sync_reserves(self.token_a_vault.amount, self.token_b_vault.amount)?;
+
+        // --- Emit Event ---
+        // Log the details of the swap event
+        emit!(SwapEvent {
+            pool: self.pool.key(),
+            user: self.user_authority.key(),
+            amount_in,
+            amount_out,
+            source_mint: self.source_mint.key(),
+            destination_mint: self.destination_mint.key(),
+            fee_amount,
+            price_impact_bps,
+        });
+
+        Ok(())
+    }
+}
+
+
+/// Defines the accounts required for the `route_swap` instruction.
+/// The pools along the route are passed in `remaining_accounts`.
+#[derive(Accounts)]
+pub struct RouteSwap<'info> {
+    /// The program config; swaps stop while it is paused.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ SwapError::Paused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The mint account for the token being sent *in* to the first pool.
+    pub source_mint: InterfaceAccount<'info, Mint>,
+    /// The mint account for the token paid *out* by the last pool.
+    pub destination_mint: InterfaceAccount<'info, Mint>,
+
+    /// The user's token account for the token they are sending *in*.
+    #[account(
+        mut,
+        constraint = user_source_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_source_token_account.mint == source_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_source_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account for the token they are receiving *out*.
+    #[account(
+        mut,
+        constraint = user_destination_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_destination_token_account.mint == destination_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user performing the swap (signer).
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Maximum number of pools a single `route_swap` can go through.
+pub const MAX_ROUTE_HOPS: usize = 4;
+
+/// Number of `remaining_accounts` each `route_swap` hop takes: (pool, pool_authority, vault_a, vault_b, oracle).
+pub const ROUTE_HOP_ACCOUNTS: usize = 5;
+
+/// One pool along a `route_swap`, validated the same way the `Swap` constraints validate a pool.
+pub struct RouteHop<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+    pub pool_authority: AccountInfo<'info>,
+    /// The pool's TWAP oracle.
+    pub oracle: Box<Account<'info, PriceOracle>>,
+    /// The pool's vault receiving this hop's input.
+    pub vault_in: InterfaceAccount<'info, TokenAccount>,
+    /// The pool's vault paying out this hop's output.
+    pub vault_out: InterfaceAccount<'info, TokenAccount>,
+    pub is_a_to_b: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub protocol_fee: u64,
+    pub price_impact_bps: u64,
+}
+
+impl<'info> RouteHop<'info> {
+    /// Loads and validates one `(pool, pool_authority, vault_a, vault_b, oracle)` group and quotes
+    /// swapping `amount_in` of `input_mint` through it.
+    fn load(group: &'info [AccountInfo<'info>], input_mint: Pubkey, amount_in: u64) -> Result<Self> {
+        let mut pool = Account::<LiquidityPool>::try_from(&group[0])?;
+        let pool_authority = group[1].clone();
+        let token_a_vault = InterfaceAccount::<TokenAccount>::try_from(&group[2])?;
+        let token_b_vault = InterfaceAccount::<TokenAccount>::try_from(&group[3])?;
+        let mut oracle = Box::new(Account::<PriceOracle>::try_from(&group[4])?);
+
+        // --- Security Checks (mirror the `Swap` constraints) ---
+        if !group[0].is_writable || !group[2].is_writable || !group[3].is_writable || !group[4].is_writable {
+            return err!(SwapError::InvalidRoute);
+        }
+        if oracle.pool != pool.key() {
+            return err!(SwapError::InvalidOracle);
+        }
+        if pool.paused {
+            return err!(SwapError::Paused);
+        }
+        let expected_authority = Pubkey::create_program_address(
+            &[
+                b"authority",
+                pool.key().as_ref(),
+                &[pool.authority_bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| error!(SwapError::InvalidVault))?;
+        if pool_authority.key() != expected_authority {
+            return err!(SwapError::InvalidVault);
+        }
+        if token_a_vault.key() != pool.token_a_vault || token_b_vault.key() != pool.token_b_vault {
+            return err!(SwapError::InvalidVault);
+        }
+        if token_a_vault.owner != pool_authority.key() || token_b_vault.owner != pool_authority.key() {
+            return err!(SwapError::InvalidVault);
+        }
+        if token_a_vault.mint != pool.token_a_mint || token_b_vault.mint != pool.token_b_mint {
+            return err!(SwapError::InvalidMint);
+        }
+        if input_mint != pool.token_a_mint && input_mint != pool.token_b_mint {
+            return err!(SwapError::InvalidMint);
+        }
+        let is_a_to_b = input_mint == pool.token_a_mint;
+
+        // --- Update Oracle ---
+        // Record the price the pool held up to now, before this hop moves it
+        oracle.update(&mut pool)?;
+        pool.decay_volatility(Clock::get()?.unix_timestamp);
+
+        // --- Get Reserves ---
+        let (reserve_a, reserve_b) = pool.trading_reserves(token_a_vault.amount, token_b_vault.amount)?;
+        let (reserve_in, reserve_out) = if is_a_to_b { (reserve_a, reserve_b) } else { (reserve_b, reserve_a) };
+        if reserve_in == 0 || reserve_out == 0 {
+            return err!(SwapError::PoolIsEmpty);
+        }
+
+        // --- Swap Calculation ---
+        let amount_out = pool.swap_output(amount_in, reserve_in, reserve_out, is_a_to_b)?;
+        if amount_out == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        let price_impact_bps = pool.price_impact_bps(amount_in, amount_out, reserve_in, reserve_out, is_a_to_b)?;
+        check_price_impact(price_impact_bps, pool.max_price_impact_bps)?;
+        let fee_amount = pool.fee_amount(amount_in)?;
+        let protocol_fee = pool.protocol_fee(amount_in)?;
+
+        // --- Track Volatility ---
+        let new_reserve_in = reserve_in
+            .checked_add(amount_in)
+            .and_then(|value| value.checked_sub(protocol_fee))
+            .ok_or(SwapError::CalculationOverflow)?;
+        pool.record_price_move(reserve_in, reserve_out, new_reserve_in, reserve_out - amount_out)?;
+
+        let (vault_in, vault_out) = if is_a_to_b { (token_a_vault, token_b_vault) } else { (token_b_vault, token_a_vault) };
+        Ok(Self {
+            pool,
+            pool_authority,
+            oracle,
+            vault_in,
+            vault_out,
+            is_a_to_b,
+            amount_in,
+            amount_out,
+            fee_amount,
+            protocol_fee,
+            price_impact_bps,
+        })
+    }
+
+    /// Sends this hop's output to `to`, signed by the pool authority, and accrues the protocol fee.
+    fn pay_out(
+        &mut self,
+        to: AccountInfo<'info>,
+        mint: AccountInfo<'info>,
+        decimals: u8,
+        token_program: &Interface<'info, TokenInterface>,
+    ) -> Result<()> {
+        let pool = &self.pool;
+        let pool_key = pool.key();
+        let authority_signer_seeds = pool.authority_signer_seeds(&pool_key);
+        let signer = &[&authority_signer_seeds[..]];
+
+        let transfer_out_accounts = TransferChecked {
+            from: self.vault_out.to_account_info(),
+            mint,
+            to,
+            authority: self.pool_authority.clone(),
+        };
+        let transfer_out_cpi = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            transfer_out_accounts,
+            signer,
+        );
+        transfer_checked(transfer_out_cpi, self.amount_out, decimals)?;
+
+        // --- Accrue Protocol Fee ---
+        // Accounts loaded from remaining_accounts aren't persisted automatically, so write the pool and its oracle back
+        let pool = &mut self.pool;
+        if self.is_a_to_b {
+            pool.protocol_fees_a = pool.protocol_fees_a.checked_add(self.protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        } else {
+            pool.protocol_fees_b = pool.protocol_fees_b.checked_add(self.protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        }
+
+        // --- Sync Reserves ---
+        // The input already arrived from the user or the previous hop, so both vaults are final
+        self.vault_in.reload()?;
+        self.vault_out.reload()?;
+        let (vault_a_amount, vault_b_amount) = if self.is_a_to_b {
+            (self.vault_in.amount, self.vault_out.amount)
+        } else {
+            (self.vault_out.amount, self.vault_in.amount)
+        };
+        pool.sync_reserves(vault_a_amount, vault_b_amount)?;
+        pool.exit(&crate::ID)?;
+        self.oracle.exit(&crate::ID)
+    }
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+        constraint = !pool.paused @ SwapError::Paused,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's TWAP oracle, updated before the reserves change.
+    #[account(
+        mut,
+        has_one = pool @ SwapError::InvalidOracle,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+
+    /// The program config; swaps and deposits stop while it is paused.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ SwapError::Paused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// CHECK: This is the pool authority PDA that's derived deterministically.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_a_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_b_account.owner == user_authority.key() @
+        SwapError::InvalidOwner,
+
+        constraint = user_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint @ SwapError::InvalidLpMint,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_lp_token_account.mint == lp_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_lp_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+
+/// Defines the accounts required for the `zap_in` instruction.
+#[derive(Accounts)]
+pub struct ZapIn<'info> {
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+        constraint = !pool.paused @ SwapError::Paused,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's TWAP oracle, updated before the reserves change.
+    #[account(
+        mut,
+        has_one = pool @ SwapError::InvalidOracle,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+
+    /// The program config; swaps and deposits stop while it is paused.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ SwapError::Paused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing the LP mint.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// The user's token account for the single token being deposited.
+    #[account(
+        mut,
+        constraint = user_source_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+    )]
+    pub user_source_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint @ SwapError::InvalidLpMint,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_lp_token_account.mint == lp_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_lp_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `remove_liquidity` instruction.
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's TWAP oracle, updated before the reserves change.
+    #[account(
+        mut,
+        has_one = pool @ SwapError::InvalidOracle,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint @ SwapError::InvalidLpMint,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_a_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_b_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_lp_token_account.mint == lp_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_lp_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `zap_out` instruction.
+#[derive(Accounts)]
+pub struct ZapOut<'info> {
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's TWAP oracle, updated before the reserves change.
+    #[account(
+        mut,
+        has_one = pool @ SwapError::InvalidOracle,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint @ SwapError::InvalidLpMint,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_lp_token_account.mint == lp_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_lp_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account for the single token being paid out.
+    #[account(
+        mut,
+        constraint = user_destination_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+    )]
+    pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `collect_protocol_fees` instruction.
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config, naming the admin and the treasury that protocol fees go to.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The treasury's token account receiving the Token A fees.
+    #[account(
+        mut,
+        constraint = treasury_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+        constraint = treasury_token_a_account.owner == config.treasury @ SwapError::InvalidOwner,
+    )]
+    pub treasury_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The treasury's token account receiving the Token B fees.
+    #[account(
+        mut,
+        constraint = treasury_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+        constraint = treasury_token_b_account.owner == config.treasury @ SwapError::InvalidOwner,
+    )]
+    pub treasury_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The config admin (signer).
+    pub admin: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `set_max_price_impact` instruction.
+#[derive(Accounts)]
+pub struct SetMaxPriceImpact<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The config admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `set_concentrated_max_price_impact` instruction.
+#[derive(Accounts)]
+pub struct SetConcentratedMaxPriceImpact<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+
+    /// The config admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `propose_admin` instruction.
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The current config admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `accept_admin` instruction.
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.pending_admin == Some(new_admin.key()) @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The proposed admin (signer).
+    pub new_admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `set_paused` instruction.
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The config admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `set_pool_paused` instruction.
+#[derive(Accounts)]
+pub struct SetPoolPaused<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The config admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `set_concentrated_pool_paused` instruction.
+#[derive(Accounts)]
+pub struct SetConcentratedPoolPaused<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+
+    /// The config admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `get_twap` instruction.
+#[derive(Accounts)]
+pub struct GetTwap<'info> {
+    /// The pool, whose synced reserves bring the price accumulators up to date.
+    pub pool: Account<'info, LiquidityPool>,
+
+    #[account(
+        has_one = pool @ SwapError::InvalidOracle,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+}
+
+/// Defines the accounts required for the `get_concentrated_twap` instruction.
+#[derive(Accounts)]
+pub struct GetConcentratedTwap<'info> {
+    /// The concentrated pool, whose sqrt price brings the price accumulators up to date.
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+
+    #[account(
+        has_one = pool @ SwapError::InvalidOracle,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+}
+
+/// Defines the accounts required for the `initialize_concentrated_pool` instruction.
+#[derive(Accounts)]
+#[instruction(tick_spacing: u16, fee_bps: Option<u16>)]
+pub struct InitializeConcentratedPool<'info> {
+    /// The program config, providing the fee tiers and the protocol's share of swap fees.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The mint account for Token A. Must sort before Token B so prices have one orientation.
+    #[account(
+        constraint = token_a_mint.key() < token_b_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    /// The mint account for Token B.
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// The ConcentratedPool account to be created.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"cl_pool",
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref(),
+            fee_bps.unwrap_or(config.default_fee_bps).to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = CONCENTRATED_POOL_ACCOUNT_SIZE,
+    )]
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+
+    /// CHECK: The authority PDA for the pool.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The associated token account (vault) for Token A, owned by the pool_authority PDA.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = token_a_mint,
+        associated_token::authority = pool_authority,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The associated token account (vault) for Token B, owned by the pool_authority PDA.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = token_b_mint,
+        associated_token::authority = pool_authority,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's TWAP oracle, holding a ring buffer of price observations.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"oracle",
+            pool.key().as_ref(),
+        ],
+        bump,
+        space = PRICE_ORACLE_ACCOUNT_SIZE,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+
+    /// Token A's pool index, created by the first pool that trades Token A.
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        seeds = [
+            b"mint_pools",
+            token_a_mint.key().as_ref(),
+        ],
+        bump,
+        space = MINT_POOL_INDEX_ACCOUNT_SIZE,
+    )]
+    pub token_a_pool_index: Box<Account<'info, MintPoolIndex>>,
+
+    /// The page of Token A's pool index this pool is listed on, created when the previous page fills up.
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        seeds = [
+            b"mint_pools",
+            token_a_mint.key().as_ref(),
+            (token_a_pool_index.pool_count / MINT_POOL_INDEX_PAGE_CAPACITY).to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = MINT_POOL_INDEX_PAGE_ACCOUNT_SIZE,
+    )]
+    pub token_a_pool_index_page: Box<Account<'info, MintPoolIndexPage>>,
+
+    /// Token B's pool index, created by the first pool that trades Token B.
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        seeds = [
+            b"mint_pools",
+            token_b_mint.key().as_ref(),
+        ],
+        bump,
+        space = MINT_POOL_INDEX_ACCOUNT_SIZE,
+    )]
+    pub token_b_pool_index: Box<Account<'info, MintPoolIndex>>,
+
+    /// The page of Token B's pool index this pool is listed on, created when the previous page fills up.
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        seeds = [
+            b"mint_pools",
+            token_b_mint.key().as_ref(),
+            (token_b_pool_index.pool_count / MINT_POOL_INDEX_PAGE_CAPACITY).to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = MINT_POOL_INDEX_PAGE_ACCOUNT_SIZE,
+    )]
+    pub token_b_pool_index_page: Box<Account<'info, MintPoolIndexPage>>,
+
+    /// The user initializing the pool (signer and payer).
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
 }
 
-/// Define the space required for the LiquidityPool account.
-const POOL_ACCOUNT_SIZE: usize = 8 + ( 32 * 4 ) + 1 + 64; // = 201 bytes
+/// Defines the accounts required for the `initialize_tick_array` instruction.
+#[derive(Accounts)]
+#[instruction(start_tick_index: i32)]
+pub struct InitializeTickArray<'info> {
+    /// The concentrated pool the ticks belong to.
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+
+    /// The TickArray account to be created, one per start index.
+    #[account(
+        init,
+        payer = payer,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &start_tick_index.to_le_bytes(),
+        ],
+        bump,
+        space = TICK_ARRAY_ACCOUNT_SIZE,
+    )]
+    pub tick_array: Box<Account<'info, TickArray>>,
+
+    /// Whoever pays for the new account.
+    #[account(mut)]
+    pub payer: Signer<'info>,
 
-/// Defines the accounts required for the `initialize_pool` instruction.
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `open_position` instruction.
 #[derive(Accounts)]
-pub struct InitializePool<'info> {
-    /// The mint account for Token A. Must be passed by the client.
-    pub token_a_mint: InterfaceAccount<'info, Mint>,
-    /// The mint account for Token B. Must be passed by the client.
-    pub token_b_mint: InterfaceAccount<'info, Mint>,
+#[instruction(tick_lower: i32, tick_upper: i32)]
+pub struct OpenPosition<'info> {
+    /// The concentrated pool the position provides liquidity to.
+    pub pool: Box<Account<'info, ConcentratedPool>>,
 
-    /// The LiquidityPool account to be created.
+    /// The Position account to be created, one per owner and range.
     #[account(
         init,
-        payer = initializer,
+        payer = owner,
         seeds = [
-            b"pool",
-            token_a_mint.key().as_ref(),
-            token_b_mint.key().as_ref(),
+            b"position",
+            pool.key().as_ref(),
+            owner.key().as_ref(),
+            &tick_lower.to_le_bytes(),
+            &tick_upper.to_le_bytes(),
         ],
         bump,
-        space = POOL_ACCOUNT_SIZE,
+        space = POSITION_ACCOUNT_SIZE,
     )]
-    pub pool: Account<'info, LiquidityPool>,
+    pub position: Account<'info, Position>,
 
-    /// CHECK: The authority PDA for the pool.
+    /// The owner of the new position (signer and payer).
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `increase_liquidity` and `decrease_liquidity` instructions.
+#[derive(Accounts)]
+pub struct ModifyLiquidity<'info> {
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+
+    /// The program config; `increase_liquidity` stops while it or the pool is paused, withdrawals stay open.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
     #[account(
         seeds = [
-            b"pool",
-            token_a_mint.key().as_ref(),
-            token_b_mint.key().as_ref(),
+            b"authority",
+            pool.key().as_ref(),
         ],
-        bump,
+        bump = pool.authority_bump,
     )]
     pub pool_authority: AccountInfo<'info>,
 
-    /// The associated token account (vault) for Token A, owned by the pool_authority PDA.
+    /// The position being changed. Only its owner may change it.
     #[account(
-        init,
-        payer = initializer,
-        associated_token::mint = token_a_mint,
-        associated_token::authority = pool_authority, // Anchor ensures this authority matches the pool_authority account provided
+        mut,
+        has_one = pool,
+        has_one = owner @ SwapError::Unauthorized,
+    )]
+    pub position: Account<'info, Position>,
+
+    /// CHECK: Loaded and checked as the pool's TickArray holding `position.tick_lower` in the handler.
+    #[account(mut)]
+    pub tick_array_lower: AccountInfo<'info>,
+
+    /// CHECK: Loaded and checked as the pool's TickArray holding `position.tick_upper` in the handler.
+    /// May be the same account as `tick_array_lower`.
+    #[account(mut)]
+    pub tick_array_upper: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_a_account.owner == owner.key() @ SwapError::InvalidOwner,
+        constraint = user_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_b_account.owner == owner.key() @ SwapError::InvalidOwner,
+        constraint = user_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
     )]
     pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The associated token account (vault) for Token B, owned by the pool_authority PDA.
     #[account(
-        init,
-        payer = initializer,
-        associated_token::mint = token_b_mint,
-        associated_token::authority = pool_authority, // Anchor ensures this authority matches the pool_authority account provided
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
     )]
     pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The user initializing the pool (signer and payer).
     #[account(mut)]
-    pub initializer: Signer<'info>,
+    pub owner: Signer<'info>,
 
-    // System Accounts
     pub token_program: Interface<'info, TokenInterface>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
 }
 
-/// Defines the accounts required for the `swap` instruction.
+impl<'info> ModifyLiquidity<'info> {
+    /// Applies `liquidity_delta` to the position, its boundary ticks and, when the range holds the
+    /// current price, the pool. Fees earned so far are credited to the position first.
+    /// Returns the Token A and B amounts the change moves in or out of the vaults.
+    fn modify_position(&mut self, liquidity_delta: i128) -> Result<(u64, u64)> {
+        let pool = &mut self.pool;
+        let position = &mut self.position;
+        let pool_key = pool.key();
+        let (tick_lower_index, tick_upper_index) = (position.tick_lower, position.tick_upper);
+
+        // --- Load Boundary Ticks ---
+        // Both ticks can live in the same array; then it is loaded and stored once.
+        let same_array = self.tick_array_lower.key() == self.tick_array_upper.key();
+        let mut lower_array = TickArray::load(&self.tick_array_lower, pool_key)?;
+        let mut upper_array = if same_array { None } else { Some(TickArray::load(&self.tick_array_upper, pool_key)?) };
+
+        let mut tick_lower = *lower_array.tick_mut(tick_lower_index, pool.tick_spacing)?;
+        let mut tick_upper = *upper_array
+            .as_deref_mut()
+            .unwrap_or(&mut *lower_array)
+            .tick_mut(tick_upper_index, pool.tick_spacing)?;
+
+        // --- Update Ticks And Settle Fees ---
+        let (global_a, global_b) = (pool.fee_growth_global_a_x64, pool.fee_growth_global_b_x64);
+        tick_lower.update(tick_lower_index, pool.tick_current, liquidity_delta, global_a, global_b, false)?;
+        tick_upper.update(tick_upper_index, pool.tick_current, liquidity_delta, global_a, global_b, true)?;
+
+        let (inside_a, inside_b) = fee_growth_inside(
+            tick_lower_index,
+            &tick_lower,
+            tick_upper_index,
+            &tick_upper,
+            pool.tick_current,
+            global_a,
+            global_b,
+        );
+        position.settle_fees(inside_a, inside_b)?;
+        position.liquidity = add_liquidity_delta(position.liquidity, liquidity_delta)?;
+
+        // Ticks no position references any more go back to their empty state
+        if tick_lower.liquidity_gross == 0 {
+            tick_lower = Tick::default();
+        }
+        if tick_upper.liquidity_gross == 0 {
+            tick_upper = Tick::default();
+        }
+        *lower_array.tick_mut(tick_lower_index, pool.tick_spacing)? = tick_lower;
+        *upper_array
+            .as_deref_mut()
+            .unwrap_or(&mut *lower_array)
+            .tick_mut(tick_upper_index, pool.tick_spacing)? = tick_upper;
+        lower_array.store(&self.tick_array_lower)?;
+        if let Some(upper_array) = upper_array {
+            upper_array.store(&self.tick_array_upper)?;
+        }
+
+        // --- Token Amounts ---
+        // Deposits round up and withdrawals round down, both in the pool's favor
+        let liquidity = liquidity_delta.unsigned_abs();
+        let round_up = liquidity_delta > 0;
+        let sqrt_price_lower = sqrt_price_at_tick(tick_lower_index)?;
+        let sqrt_price_upper = sqrt_price_at_tick(tick_upper_index)?;
+        let (amount_a, amount_b) = if pool.tick_current < tick_lower_index {
+            // Price below the range: the position is all Token A
+            (token_a_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up)?, 0)
+        } else if pool.tick_current < tick_upper_index {
+            // Price inside the range: both tokens, and the liquidity is active
+            pool.liquidity = add_liquidity_delta(pool.liquidity, liquidity_delta)?;
+            (
+                token_a_delta(pool.sqrt_price_x64, sqrt_price_upper, liquidity, round_up)?,
+                token_b_delta(sqrt_price_lower, pool.sqrt_price_x64, liquidity, round_up)?,
+            )
+        } else {
+            // Price above the range: the position is all Token B
+            (0, token_b_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up)?)
+        };
+
+        Ok((
+            u64::try_from(amount_a).map_err(|_| error!(SwapError::CalculationOverflow))?,
+            u64::try_from(amount_b).map_err(|_| error!(SwapError::CalculationOverflow))?,
+        ))
+    }
+}
+
+/// Defines the accounts required for the `swap_concentrated` instruction.
+/// The tick arrays the swap walks through are passed as `remaining_accounts`.
 #[derive(Accounts)]
-pub struct Swap<'info> {
+pub struct SwapConcentrated<'info> {
     /// The mint account for the token being sent *in*.
     pub source_mint: InterfaceAccount<'info, Mint>,
     /// The mint account for the token being sent *out*.
     pub destination_mint: InterfaceAccount<'info, Mint>,
 
-    /// The LiquidityPool account containing the state for this swap.
+    /// The ConcentratedPool account; its price, liquidity and fee growth change with the swap.
     #[account(
-        // REMOVED seeds and bump validation from here.
-        // We validate the pool implicitly through the pool_authority check and vault constraints.
-        // --- Security Constraints ---
-        // These constraints ensure the provided vaults match the addresses stored in the pool state.
+        mut,
         constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
         constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
-        constraint = (pool.token_a_mint == source_mint.key() && pool.token_b_mint == destination_mint.key()) || 
-                    (pool.token_a_mint == destination_mint.key() && pool.token_b_mint == source_mint.key()) 
+        constraint = (pool.token_a_mint == source_mint.key() && pool.token_b_mint == destination_mint.key()) ||
+                    (pool.token_a_mint == destination_mint.key() && pool.token_b_mint == source_mint.key())
                     @ SwapError::InvalidMint,
+        constraint = !pool.paused @ SwapError::Paused,
     )]
-    pub pool: Account<'info, LiquidityPool>,
+    pub pool: Box<Account<'info, ConcentratedPool>>,
+
+    /// The pool's TWAP oracle, updated before the price moves.
+    #[account(
+        mut,
+        has_one = pool @ SwapError::InvalidOracle,
+    )]
+    pub oracle: Box<Account<'info, PriceOracle>>,
+
+    /// The program config; swaps stop while it is paused.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ SwapError::Paused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
 
     /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
     #[account(
         seeds = [
-            b"pool", 
-            pool.token_a_mint.as_ref(),
-            pool.token_b_mint.as_ref(),
+            b"authority",
+            pool.key().as_ref(),
         ],
-        bump = pool.bump,
+        bump = pool.authority_bump,
     )]
     pub pool_authority: AccountInfo<'info>,
 
@@ -485,23 +5395,17 @@ pub struct Swap<'info> {
     )]
     pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// The pool's vault for Token A. Needs to be mutable for balance changes.
+    /// The pool's vault for Token A.
     #[account(
         mut,
-        // Constraint to ensure this vault belongs to the validated pool authority PDA.
         constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
-        // Constraint ensuring the vault's mint matches the pool state's mint A.
-        constraint = token_a_vault.mint == pool.token_a_mint @ SwapError::InvalidMint,
     )]
     pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The pool's vault for Token B. Needs to be mutable for balance changes.
+    /// The pool's vault for Token B.
     #[account(
         mut,
-        // Constraint to ensure this vault belongs to the validated pool authority PDA.
         constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
-        // Constraint ensuring the vault's mint matches the pool state's mint B.
-        constraint = token_b_vault.mint == pool.token_b_mint @ SwapError::InvalidMint,
     )]
     pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
 
@@ -513,26 +5417,15 @@ pub struct Swap<'info> {
     pub token_program: Interface<'info, TokenInterface>,
 }
 
-
+/// Defines the accounts required for the `collect_concentrated_protocol_fees` instruction.
 #[derive(Accounts)]
-pub struct AddLiquidity<'info> {
+pub struct CollectConcentratedProtocolFees<'info> {
     #[account(
+        mut,
         constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
-
         constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
     )]
-    pub pool: Account<'info, LiquidityPool>,
-
-    /// CHECK: This is the pool authority PDA that's derived deterministically.
-    #[account(
-        seeds = [
-            b"pool",
-            pool.token_a_mint.as_ref(),
-            pool.token_b_mint.as_ref(),
-        ],
-        bump,
-    )]
-    pub pool_authority: AccountInfo<'info>,
+    pub pool: Box<Account<'info, ConcentratedPool>>,
 
     #[account(
         constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
@@ -544,41 +5437,58 @@ pub struct AddLiquidity<'info> {
     )]
     pub token_b_mint: InterfaceAccount<'info, Mint>,
 
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"authority",
+            pool.key().as_ref(),
+        ],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
     #[account(
         mut,
-        constraint = user_token_a_account.owner == user_authority.key() @ SwapError::InvalidOwner,
-        constraint = user_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
     )]
-    pub user_token_a_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
-        constraint = user_token_b_account.owner == user_authority.key() @
-        SwapError::InvalidOwner,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
 
-        constraint = user_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+    /// The program config, naming the admin and the treasury that protocol fees go to.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
     )]
-    pub user_token_b_account: InterfaceAccount<'info, TokenAccount>,
+    pub config: Account<'info, ProgramConfig>,
 
+    /// The treasury's token account receiving the Token A fees.
     #[account(
         mut,
-        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+        constraint = treasury_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+        constraint = treasury_token_a_account.owner == config.treasury @ SwapError::InvalidOwner,
     )]
-    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+    pub treasury_token_a_account: InterfaceAccount<'info, TokenAccount>,
 
+    /// The treasury's token account receiving the Token B fees.
     #[account(
         mut,
-        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+        constraint = treasury_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+        constraint = treasury_token_b_account.owner == config.treasury @ SwapError::InvalidOwner,
     )]
-    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+    pub treasury_token_b_account: InterfaceAccount<'info, TokenAccount>,
 
-    #[account(mut)]
-    pub user_authority: Signer<'info>,
+    /// The config admin (signer).
+    pub admin: Signer<'info>,
 
     pub token_program: Interface<'info, TokenInterface>,
 }
 
-
 /// Defines the accounts required for the `process_transaction` instruction.
 #[derive(Accounts)]
 #[instruction(amount: u64)]
@@ -634,6 +5544,27 @@ pub struct SwapEvent {
     pub source_mint: Pubkey,
     /// The mint of the token sent out of the pool.
     pub destination_mint: Pubkey,
+    /// The swap fee charged on `amount_in` (LP and protocol share), in the input token.
+    pub fee_amount: u64,
+    /// How much worse the execution price (fee excluded) was than the pre-trade spot price, in basis points.
+    pub price_impact_bps: u64,
+}
+
+/// Event emitted when a pool is created and seeded with its first liquidity.
+/// Mints and amounts are in pool order; `lp_amount` excludes the locked `MINIMUM_LIQUIDITY` shares.
+/// `initial_price` is Token A's starting price in Token B, in 18-decimal fixed point whole tokens.
+#[event]
+pub struct PoolCreatedEvent {
+    pub pool: Pubkey,
+    pub creator: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub fee_tier_bps: u16,
+    pub curve_type: CurveType,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub lp_amount: u64,
+    pub initial_price: u128,
 }
 
 #[event]
@@ -642,6 +5573,73 @@ pub struct LiquidityAddedEvent {
     pub user: Pubkey,
     pub amount_a: u64,
     pub amount_b: u64,
+    pub lp_amount: u64,
+}
+
+/// Event emitted when LP shares are burned for the underlying tokens.
+#[event]
+pub struct LiquidityRemovedEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub lp_amount: u64,
+}
+
+/// Event emitted when accrued protocol fees are moved to the treasury.
+#[event]
+pub struct ProtocolFeesCollectedEvent {
+    pub pool: Pubkey,
+    pub admin: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+/// Event emitted when the config admin changes a pool's price impact limit.
+#[event]
+pub struct MaxPriceImpactUpdatedEvent {
+    pub pool: Pubkey,
+    pub admin: Pubkey,
+    pub old_max_price_impact_bps: u16,
+    pub new_max_price_impact_bps: u16,
+}
+
+/// Event emitted when the config admin proposes a new admin.
+#[event]
+pub struct AdminProposedEvent {
+    pub admin: Pubkey,
+    pub proposed_admin: Pubkey,
+}
+
+/// Event emitted when a proposed admin accepts the config admin role.
+#[event]
+pub struct AdminTransferredEvent {
+    pub old_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+/// Event emitted when the config admin pauses or unpauses swaps and deposits.
+/// `pool` is `None` for the global pause.
+#[event]
+pub struct PauseUpdatedEvent {
+    pub admin: Pubkey,
+    pub pool: Option<Pubkey>,
+    pub paused: bool,
+}
+
+/// Event emitted when liquidity is added to or removed from a concentrated liquidity position.
+#[event]
+pub struct PositionLiquidityChangedEvent {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    /// Positive when liquidity was added, negative when it was removed.
+    pub liquidity_delta: i128,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    /// Fees paid out along with a withdrawal.
+    pub fees_a: u64,
+    pub fees_b: u64,
 }
 
 // --- Errors ---
@@ -669,4 +5667,204 @@ pub enum SwapError {
     ExcessivePriceImpact,
     #[msg("Disproportionate liquidity provided")]
     DisproportionateLiquidity,
-}
\ No newline at end of file
+    #[msg("Invalid LP mint provided.")]
+    InvalidLpMint,
+    #[msg("Not enough LP shares in the pool.")]
+    InsufficientLiquidity,
+    #[msg("Invalid fee: denominator must be non-zero and the fee below 100%.")]
+    InvalidFee,
+    #[msg("Signer is not authorized to perform this action.")]
+    Unauthorized,
+    #[msg("Invalid swap route.")]
+    InvalidRoute,
+    #[msg("Transaction deadline has passed.")]
+    Expired,
+    #[msg("Invalid curve parameters.")]
+    InvalidCurveParameters,
+    #[msg("Invalid tick range.")]
+    InvalidTickRange,
+    #[msg("Invalid or missing tick array.")]
+    InvalidTickArray,
+    #[msg("Sqrt price out of range.")]
+    InvalidSqrtPrice,
+    #[msg("Oracle account does not belong to this pool.")]
+    InvalidOracle,
+    #[msg("TWAP window must be greater than zero.")]
+    InvalidTwapWindow,
+    #[msg("Not enough oracle history for the requested window.")]
+    InsufficientOracleHistory,
+    #[msg("Price impact limit must be at most 10000 bps.")]
+    InvalidPriceImpactLimit,
+    #[msg("Fee is not one of the allowed fee tiers.")]
+    InvalidFeeTier,
+    #[msg("Swaps and deposits are paused.")]
+    Paused,
+    #[msg("Deposit is too small to mint any LP shares.")]
+    ZeroLpShares,
+    #[msg("Initial deposit must mint more than the locked minimum liquidity.")]
+    InsufficientInitialLiquidity,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Q64: u128 = 1 << 64;
+
+    #[test]
+    fn sqrt_price_at_tick_matches_known_values() {
+        assert_eq!(sqrt_price_at_tick(0).unwrap(), Q64);
+        assert_eq!(sqrt_price_at_tick(MIN_TICK).unwrap(), MIN_SQRT_PRICE_X64);
+        assert_eq!(sqrt_price_at_tick(MAX_TICK).unwrap(), MAX_SQRT_PRICE_X64);
+
+        // sqrt(1.0001^tick) * 2^64, rounded up
+        for (tick, expected) in [
+            (1, 18_447_666_387_855_959_851u128),
+            (-1, 18_445_821_805_675_392_312),
+            (10_000, 30_412_779_051_191_548_723),
+            (-10_000, 11_188_795_550_323_325_958),
+        ] {
+            let sqrt_price = sqrt_price_at_tick(tick).unwrap();
+            assert!(sqrt_price.abs_diff(expected) <= 1, "tick {}: {} != {}", tick, sqrt_price, expected);
+        }
+    }
+
+    #[test]
+    fn sqrt_price_at_tick_rejects_ticks_out_of_range() {
+        assert!(sqrt_price_at_tick(MIN_TICK - 1).is_err());
+        assert!(sqrt_price_at_tick(MAX_TICK + 1).is_err());
+    }
+
+    #[test]
+    fn tick_at_sqrt_price_inverts_sqrt_price_at_tick() {
+        for tick in [MIN_TICK, -443_635, -100_000, -64, -1, 0, 1, 64, 100_000, 443_635, MAX_TICK] {
+            let sqrt_price = sqrt_price_at_tick(tick).unwrap();
+            assert_eq!(tick_at_sqrt_price(sqrt_price).unwrap(), tick);
+            // Just below a tick's price is still the tick before it
+            if tick > MIN_TICK {
+                assert_eq!(tick_at_sqrt_price(sqrt_price - 1).unwrap(), tick - 1);
+            }
+        }
+        assert!(tick_at_sqrt_price(MIN_SQRT_PRICE_X64 - 1).is_err());
+        assert!(tick_at_sqrt_price(MAX_SQRT_PRICE_X64 + 1).is_err());
+    }
+
+    #[test]
+    fn token_deltas_round_in_the_pools_favor() {
+        let lower = sqrt_price_at_tick(-128).unwrap();
+        let upper = sqrt_price_at_tick(128).unwrap();
+        let liquidity = 1_000_000_007u128;
+
+        let a_down = token_a_delta(lower, upper, liquidity, false).unwrap();
+        let a_up = token_a_delta(upper, lower, liquidity, true).unwrap();
+        assert!(a_up == a_down || a_up == a_down + 1);
+
+        let b_down = token_b_delta(lower, upper, liquidity, false).unwrap();
+        let b_up = token_b_delta(upper, lower, liquidity, true).unwrap();
+        assert!(b_up == b_down || b_up == b_down + 1);
+    }
+
+    #[test]
+    fn next_sqrt_price_moves_with_the_input_token() {
+        let liquidity = 1_000_000_000u128;
+        assert!(next_sqrt_price_from_input(Q64, liquidity, 1_000, true).unwrap() < Q64);
+        assert!(next_sqrt_price_from_input(Q64, liquidity, 1_000, false).unwrap() > Q64);
+        assert_eq!(next_sqrt_price_from_input(Q64, liquidity, 0, true).unwrap(), Q64);
+        assert!(next_sqrt_price_from_input(Q64, 0, 1_000, true).is_err());
+    }
+
+    #[test]
+    fn swap_step_uses_the_whole_input_inside_a_range() {
+        let target = sqrt_price_at_tick(-1_024).unwrap();
+        let liquidity = 10_000_000_000u128;
+        let step = compute_swap_step(Q64, target, liquidity, 1_000_000, 30, 10_000, true).unwrap();
+
+        assert!(step.next_sqrt_price_x64 < Q64 && step.next_sqrt_price_x64 > target);
+        assert_eq!(step.amount_in + step.fee_amount, 1_000_000);
+        assert!(step.fee_amount >= 1_000_000 * 30 / 10_000);
+        // Selling Token A at a price at or below 1.0 can't return more Token B than went in
+        assert!(step.amount_out <= step.amount_in);
+        assert_eq!(
+            step.amount_out,
+            token_b_delta(step.next_sqrt_price_x64, Q64, liquidity, false).unwrap()
+        );
+    }
+
+    #[test]
+    fn swap_step_stops_at_the_target_price() {
+        let target = sqrt_price_at_tick(64).unwrap();
+        let liquidity = 1_000_000u128;
+        let step = compute_swap_step(Q64, target, liquidity, u64::MAX as u128, 30, 10_000, false).unwrap();
+
+        assert_eq!(step.next_sqrt_price_x64, target);
+        assert_eq!(step.amount_in, token_b_delta(Q64, target, liquidity, true).unwrap());
+        assert_eq!(step.fee_amount, (step.amount_in * 30).div_ceil(10_000 - 30));
+        assert_eq!(step.amount_out, token_a_delta(Q64, target, liquidity, false).unwrap());
+    }
+
+    #[test]
+    fn swap_step_without_liquidity_moves_the_price_for_free() {
+        let target = sqrt_price_at_tick(-64).unwrap();
+        let step = compute_swap_step(Q64, target, 0, 1_000_000, 30, 10_000, true).unwrap();
+
+        assert_eq!(step.next_sqrt_price_x64, target);
+        assert_eq!(step.amount_in, 0);
+        assert_eq!(step.amount_out, 0);
+        assert_eq!(step.fee_amount, 0);
+    }
+
+    fn observation(timestamp: i64) -> Observation {
+        // A constant price of 1.0 in Q64.64
+        Observation {
+            timestamp,
+            price_a_cumulative_x64: Q64 * timestamp as u128,
+            price_b_cumulative_x64: Q64 * timestamp as u128,
+        }
+    }
+
+    #[test]
+    fn oracle_keeps_its_guaranteed_window_through_a_burst_of_updates() {
+        let mut oracle = PriceOracle {
+            pool: Pubkey::default(),
+            observation_index: 0,
+            observation_count: 0,
+            observations: [Observation::default(); OBSERVATION_CAPACITY],
+        };
+        // One update every second for well over the buffer's worth of intervals
+        let start = 1_000_000;
+        let end = start + 4 * OBSERVATION_CAPACITY as i64 * MIN_OBSERVATION_INTERVAL_SECONDS;
+        for now in start..=end {
+            oracle.record(observation(now));
+        }
+
+        assert_eq!(oracle.observation_count as usize, OBSERVATION_CAPACITY);
+        let guaranteed = (OBSERVATION_CAPACITY as i64 - 1) * MIN_OBSERVATION_INTERVAL_SECONDS;
+        let (price_a_x64, price_b_x64) = oracle.twap((Q64 * end as u128, Q64 * end as u128), end, guaranteed as u32).unwrap();
+        assert_eq!(price_a_x64, Q64);
+        assert_eq!(price_b_x64, Q64);
+    }
+
+    #[test]
+    fn oracle_skips_observations_closer_than_the_minimum_interval() {
+        let mut oracle = PriceOracle {
+            pool: Pubkey::default(),
+            observation_index: 0,
+            observation_count: 0,
+            observations: [Observation::default(); OBSERVATION_CAPACITY],
+        };
+        oracle.record(observation(100));
+        oracle.record(observation(100 + MIN_OBSERVATION_INTERVAL_SECONDS - 1));
+        assert_eq!(oracle.observation_count, 1);
+
+        oracle.record(observation(100 + MIN_OBSERVATION_INTERVAL_SECONDS));
+        assert_eq!(oracle.observation_count, 2);
+        assert_eq!(oracle.observations[oracle.observation_index as usize].timestamp, 100 + MIN_OBSERVATION_INTERVAL_SECONDS);
+    }
+
+    #[test]
+    fn swap_step_rejects_a_target_behind_the_price() {
+        let above = sqrt_price_at_tick(64).unwrap();
+        let below = sqrt_price_at_tick(-64).unwrap();
+        assert!(compute_swap_step(Q64, above, 1_000, 1_000, 30, 10_000, true).is_err());
+        assert!(compute_swap_step(Q64, below, 1_000, 1_000, 30, 10_000, false).is_err());
+    }
+}
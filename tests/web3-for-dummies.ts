@@ -7,7 +7,7 @@ import {
     SystemProgram,
     LAMPORTS_PER_SOL,
 } from "@solana/web3.js";
-import { createMint, getAccount, getAssociatedTokenAddress, getOrCreateAssociatedTokenAccount, mintTo, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { createMint, getAccount, getAssociatedTokenAddress, getAssociatedTokenAddressSync, getMint, getOrCreateAssociatedTokenAccount, mintTo, transfer, TOKEN_PROGRAM_ID } from "@solana/spl-token";
 import { assert } from "chai";
 
 
@@ -23,8 +23,24 @@ describe("web3-for-dummies", () => {
     const alice = anchor.web3.Keypair.generate();
     const bob = anchor.web3.Keypair.generate();
     const intializer = anchor.web3.Keypair.generate();
+    const [configPda] = PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId);
 
     const decimals = 6;
+    // 0.3% swap fee for the test pool
+    const poolFeeBps = 30;
+    const poolFeeNumerator = new BN(poolFeeBps);
+    const poolFeeDenominator = new BN(10_000);
+    // Fee tiers allowed by the program config: 0.01%, 0.05%, 0.3% and 1%
+    const feeTiersBps = [1, 5, 30, 100];
+    // 20% of each swap fee goes to the protocol
+    const poolProtocolFeeShareBps = 2000;
+    const poolMaxPriceImpactBps = 1000;
+    // The initializer seeds the main pool 1:1 when creating it
+    const poolSeedAmount = new BN(100 * (10 ** decimals));
+    const constantProductCurve = { constantProduct: {} };
+    const stableSwapCurve = { stableSwap: {} };
+    const weightedCurve = { weighted: {} };
+    const fixedFee = { fixed: {} };
     const initialMintAmount = 1_000_000 * (10 ** decimals);
 
     let simpleTransferMint: PublicKey;
@@ -41,6 +57,8 @@ describe("web3-for-dummies", () => {
     let aliceTokenAAccount: PublicKey;
     let aliceTokenBAccount: PublicKey;
     let poolBump: number;
+    let lpMintPda: PublicKey;
+    let aliceLpTokenAccount: PublicKey;
 
     const getTokenBalance = async (tokenAccount: PublicKey): Promise<number> => {
         try {
@@ -76,6 +94,117 @@ describe("web3-for-dummies", () => {
     }
 
 
+    // The LP mint only exists once the pool is initialized, so Alice's LP account is created lazily.
+    const ensureAliceLpAccount = async (): Promise<PublicKey> => {
+        return (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lpMintPda, alice.publicKey)).address;
+    };
+
+    // Pools are PDAs of the sorted mint pair and the fee tier (u16, little-endian),
+    // so the same pair can have one pool per tier.
+    const poolFor = (mintA: PublicKey, mintB: PublicKey, feeBps: number = poolFeeBps): PublicKey =>
+        PublicKey.findProgramAddressSync(
+            [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer(), new BN(feeBps).toArrayLike(Buffer, "le", 2)],
+            program.programId
+        )[0];
+
+    // A pool's vaults and LP mint belong to a dedicated authority PDA derived from the pool.
+    const authorityFor = (pool: PublicKey): PublicKey =>
+        PublicKey.findProgramAddressSync([Buffer.from("authority"), pool.toBuffer()], program.programId)[0];
+
+    // Each mint has an on-chain index of the pools trading it, listed in fixed-size pages.
+    const poolIndexPageCapacity = 32;
+    const poolIndexFor = (mint: PublicKey): PublicKey =>
+        PublicKey.findProgramAddressSync([Buffer.from("mint_pools"), mint.toBuffer()], program.programId)[0];
+    const poolIndexPageFor = (mint: PublicKey, page: number): PublicKey =>
+        PublicKey.findProgramAddressSync(
+            [Buffer.from("mint_pools"), mint.toBuffer(), new BN(page).toArrayLike(Buffer, "le", 4)],
+            program.programId
+        )[0];
+    // A new pool is listed on the page after the ones its mints' indexes have already filled.
+    const poolIndexAccountsFor = async (mintA: PublicKey, mintB: PublicKey) => {
+        const currentPage = async (mint: PublicKey) => {
+            const index = await program.account.mintPoolIndex.fetchNullable(poolIndexFor(mint));
+            return poolIndexPageFor(mint, Math.floor((index?.poolCount ?? 0) / poolIndexPageCapacity));
+        };
+        return {
+            tokenAPoolIndex: poolIndexFor(mintA),
+            tokenAPoolIndexPage: await currentPage(mintA),
+            tokenBPoolIndex: poolIndexFor(mintB),
+            tokenBPoolIndexPage: await currentPage(mintB),
+        };
+    };
+
+    // Every constant-function pool has a TWAP oracle at a PDA derived from the pool.
+    const oracleFor = (pool: PublicKey): PublicKey =>
+        PublicKey.findProgramAddressSync([Buffer.from("oracle"), pool.toBuffer()], program.programId)[0];
+
+    // The initializer seeds new pools from its own token accounts and receives the first LP shares.
+    const intializerAtaFor = (mint: PublicKey): PublicKey => getAssociatedTokenAddressSync(mint, intializer.publicKey);
+
+    // MINIMUM_LIQUIDITY LP shares are locked for good in the pool authority's LP token account.
+    const minimumLiquidity = 1_000;
+    const lockedLpFor = (lpMint: PublicKey, poolAuthority: PublicKey): PublicKey =>
+        getAssociatedTokenAddressSync(lpMint, poolAuthority, true);
+
+    const fundIntializer = async (mint: PublicKey, amount: number | bigint) => {
+        const ata = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, mint, intializer.publicKey)).address;
+        if (BigInt(amount) > 0) {
+            await mintTo(provider.connection, payer, mint, ata, mintAuthority, BigInt(amount));
+        }
+        return ata;
+    };
+
+    // Creates a standalone pool for the given mints, seeded by the initializer with `amount` of each.
+    const createSeededPool = async (
+        mintX: PublicKey,
+        mintY: PublicKey,
+        amount: BN,
+        curveType: object = constantProductCurve,
+        amp: BN = new BN(0),
+        tokenAWeightBps: number = 0,
+        feeMode: object = fixedFee,
+        maxPriceImpactBps: number = poolMaxPriceImpactBps,
+        feeBps: number = poolFeeBps,
+    ) => {
+        const [mintA, mintB] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+        const pool = poolFor(mintA, mintB, feeBps);
+        const [lpMint] = PublicKey.findProgramAddressSync([Buffer.from("lp_mint"), pool.toBuffer()], program.programId);
+        const poolAuthority = authorityFor(pool);
+        const vaultA = await getAssociatedTokenAddress(mintA, poolAuthority, true);
+        const vaultB = await getAssociatedTokenAddress(mintB, poolAuthority, true);
+        await fundIntializer(mintA, amount.toString());
+        await fundIntializer(mintB, amount.toString());
+
+        await program.methods
+            .initializePool(feeBps, curveType, amp, tokenAWeightBps, feeMode, maxPriceImpactBps, amount, amount)
+            .accounts({
+                config: configPda,
+                initializer: intializer.publicKey,
+                tokenAMint: mintA,
+                tokenBMint: mintB,
+                pool,
+                oracle: oracleFor(pool),
+                ...(await poolIndexAccountsFor(mintA, mintB)),
+                initializerTokenAAccount: intializerAtaFor(mintA),
+                initializerTokenBAccount: intializerAtaFor(mintB),
+                initializerLpTokenAccount: intializerAtaFor(lpMint),
+                lockedLpAccount: lockedLpFor(lpMint, poolAuthority),
+                poolAuthority,
+                tokenAVault: vaultA,
+                tokenBVault: vaultB,
+                lpMint,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            } as any)
+            .signers([intializer])
+            .rpc();
+
+        const aliceLp = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lpMint, alice.publicKey)).address;
+
+        return { pool, poolAuthority, mintA, mintB, vaultA, vaultB, lpMint, aliceLp };
+    };
+
     const setupToken = async (authority: Keypair, recipient: PublicKey, amount: number): Promise<{ mint: PublicKey, ata: PublicKey }> => {
         const mint = await createMint(
             provider.connection,
@@ -136,6 +265,8 @@ describe("web3-for-dummies", () => {
 
         await mintTo(provider.connection, payer, tokenAMint, aliceTokenAAccount, mintAuthority, BigInt(initialMintAmount));
         await mintTo(provider.connection, payer, tokenBMint, aliceTokenBAccount, mintAuthority, BigInt(initialMintAmount));
+        await fundIntializer(tokenAMint, initialMintAmount);
+        await fundIntializer(tokenBMint, initialMintAmount);
 
 
         const [mintAkey, mintBKey] = [tokenAMint, tokenBMint].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
@@ -145,22 +276,21 @@ describe("web3-for-dummies", () => {
                 Buffer.from("pool"),
                 mintAkey.toBuffer(),
                 mintBKey.toBuffer(),
+                new BN(poolFeeBps).toArrayLike(Buffer, "le", 2),
             ],
             program.programId
         );
 
-        [poolAuthorityPda] = PublicKey.findProgramAddressSync(
-            [
-                Buffer.from("pool"),
-                mintAkey.toBuffer(),
-                mintBKey.toBuffer(),
-            ],
-            program.programId
-        );
+        poolAuthorityPda = authorityFor(poolPda);
 
         poolTokenAVault = await getAssociatedTokenAddress(mintAkey, poolAuthorityPda, true);
         poolTokenBVault = await getAssociatedTokenAddress(mintBKey, poolAuthorityPda, true);
 
+        [lpMintPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("lp_mint"), poolPda.toBuffer()],
+            program.programId
+        );
+        aliceLpTokenAccount = await getAssociatedTokenAddress(lpMintPda, alice.publicKey);
 
     });
 
@@ -257,6 +387,79 @@ describe("web3-for-dummies", () => {
     });
 
 
+    describe("initialize_config", () => {
+        // Only the program's upgrade authority (the provider wallet under `anchor test`) can create the config
+        const upgradeAuthority = provider.wallet.publicKey;
+        const [programDataPda] = PublicKey.findProgramAddressSync(
+            [program.programId.toBuffer()],
+            new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+        );
+        const initializeConfig = (defaultFeeBps: number, tiers: number[], admin?: Keypair) => program.methods
+            .initializeConfig(defaultFeeBps, poolProtocolFeeShareBps, intializer.publicKey, tiers)
+            .accounts({
+                config: configPda,
+                program: program.programId,
+                programData: programDataPda,
+                admin: admin ? admin.publicKey : upgradeAuthority,
+                systemProgram: SystemProgram.programId,
+            } as any)
+            .signers(admin ? [admin] : [])
+            .rpc();
+
+        it("Fails when the signer is not the upgrade authority", async () => {
+            try {
+                await initializeConfig(poolFeeBps, feeTiersBps, intializer);
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Fails when the default fee is not one of the fee tiers", async () => {
+            try {
+                await initializeConfig(25, feeTiersBps);
+                assert.fail("Should have failed due to an invalid default fee");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidFeeTier", "Expected InvalidFeeTier error");
+            }
+        });
+
+        it("Initializes the program config", async () => {
+            await initializeConfig(poolFeeBps, feeTiersBps);
+
+            const config = await program.account.programConfig.fetch(configPda);
+            assert.ok(config.admin.equals(upgradeAuthority), "Config admin mismatch");
+            assert.ok(config.treasury.equals(intializer.publicKey), "Config treasury mismatch");
+            assert.equal(config.defaultFeeBps, poolFeeBps, "Default fee mismatch");
+            assert.equal(config.protocolFeeShareBps, poolProtocolFeeShareBps, "Protocol fee share mismatch");
+            assert.deepEqual(config.feeTiersBps, feeTiersBps, "Fee tiers mismatch");
+        });
+
+        it("Fails to initialize the config twice", async () => {
+            try {
+                await initializeConfig(poolFeeBps, feeTiersBps);
+                assert.fail("Should have failed to initialize an existing config");
+            } catch (e) {
+                assert.include(e.toString(), "Simulation failed", "Expected initialization error");
+            }
+        });
+
+        it("Hands the admin role to the initializer", async () => {
+            await program.methods
+                .proposeAdmin(intializer.publicKey)
+                .accounts({ config: configPda, admin: upgradeAuthority } as any)
+                .rpc();
+            await program.methods
+                .acceptAdmin()
+                .accounts({ config: configPda, newAdmin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+
+            const config = await program.account.programConfig.fetch(configPda);
+            assert.ok(config.admin.equals(intializer.publicKey), "Config admin mismatch");
+        });
+    });
+
     describe("intialize_pool", () => {
         it("Initializes the liquidity pool correctly", async () => {
 
@@ -272,23 +475,32 @@ describe("web3-for-dummies", () => {
 
             const [mintAkey, mintBKey] = getSortedMints();
 
-            await program.methods
-                .initializePool()
+            // No fee tier given, so the pool takes the config's default fee
+            const signature = await program.methods
+                .initializePool(null, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, poolSeedAmount, poolSeedAmount)
                 .accounts({
+                    config: configPda,
                     initializer: intializer.publicKey,
                     tokenAMint: mintAkey,
                     tokenBMint: mintBKey,
                     pool: poolPda,
+                    oracle: oracleFor(poolPda),
+                    ...(await poolIndexAccountsFor(mintAkey, mintBKey)),
+                    initializerTokenAAccount: intializerAtaFor(mintAkey),
+                    initializerTokenBAccount: intializerAtaFor(mintBKey),
+                    initializerLpTokenAccount: intializerAtaFor(lpMintPda),
+                    lockedLpAccount: lockedLpFor(lpMintPda, poolAuthorityPda),
                     poolAuthority: poolAuthorityPda,
                     tokenAVault: poolTokenAVault,
                     tokenBVault: poolTokenBVault,
+                    lpMint: lpMintPda,
                     tokenProgram: TOKEN_PROGRAM_ID,
                     associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
                     systemProgram: SystemProgram.programId,
                     rent: anchor.web3.SYSVAR_RENT_PUBKEY,
                 } as any)
                 .signers([intializer])
-                .rpc();
+                .rpc({ commitment: "confirmed" });
 
             const poolAccount = await program.account.liquidityPool.fetch(poolPda);
             assert.ok(poolAccount.tokenAMint.equals(mintAkey), "Pool mint A mismatch");
@@ -296,11 +508,49 @@ describe("web3-for-dummies", () => {
             assert.ok(poolAccount.tokenAVault.equals(poolTokenAVault), "Pool vault A mismatch");
             assert.ok(poolAccount.tokenBVault.equals(poolTokenBVault), "Pool vault B mismatch");
             assert.equal(poolAccount.bump, poolBump, "Pool bump mismatch");
+            assert.ok(poolAccount.lpMint.equals(lpMintPda), "Pool LP mint mismatch");
+            assert.ok(poolAccount.feeNumerator.eq(poolFeeNumerator), "Pool fee numerator mismatch");
+            assert.ok(poolAccount.feeDenominator.eq(poolFeeDenominator), "Pool fee denominator mismatch");
+            assert.equal(poolAccount.protocolFeeShareBps, poolProtocolFeeShareBps, "Pool protocol fee share mismatch");
+
+            // The vaults belong to a dedicated authority PDA, not the pool state account
+            const [expectedAuthority, authorityBump] = PublicKey.findProgramAddressSync(
+                [Buffer.from("authority"), poolPda.toBuffer()],
+                program.programId
+            );
+            assert.ok(!expectedAuthority.equals(poolPda), "Vault authority should differ from the pool account");
+            assert.ok(poolAuthorityPda.equals(expectedAuthority), "Vault authority mismatch");
+            assert.equal(poolAccount.authorityBump, authorityBump, "Vault authority bump mismatch");
+
+            const lpMintInfo = await getMint(provider.connection, lpMintPda);
+            assert.ok(lpMintInfo.mintAuthority.equals(poolAuthorityPda), "LP mint authority mismatch");
 
             const vaultAInfo = await getAccount(provider.connection, poolTokenAVault);
             const vaultBInfo = await getAccount(provider.connection, poolTokenBVault);
             assert.ok(vaultAInfo.owner.equals(poolAuthorityPda), "Vault A owner mismatch");
             assert.ok(vaultBInfo.owner.equals(poolAuthorityPda), "Vault B owner mismatch");
+
+            // The seed deposit lands in the same instruction; the first deposit mints sqrt(a * b) LP shares,
+            // of which MINIMUM_LIQUIDITY are locked in the pool
+            const expectedLp = Math.floor(Math.sqrt(poolSeedAmount.toNumber() * poolSeedAmount.toNumber()));
+            assert.equal(Number(vaultAInfo.amount), poolSeedAmount.toNumber(), "Vault A seed mismatch");
+            assert.equal(Number(vaultBInfo.amount), poolSeedAmount.toNumber(), "Vault B seed mismatch");
+            assert.equal(Number(lpMintInfo.supply), expectedLp, "LP supply should match the seed deposit");
+            assert.equal(await getTokenBalance(intializerAtaFor(lpMintPda)), expectedLp - minimumLiquidity, "Initializer LP balance mismatch");
+            assert.equal(await getTokenBalance(lockedLpFor(lpMintPda, poolAuthorityPda)), minimumLiquidity, "Locked LP balance mismatch");
+
+            const transaction = await provider.connection.getTransaction(signature, {
+                commitment: "confirmed",
+                maxSupportedTransactionVersion: 0,
+            });
+            const events = [...new anchor.EventParser(program.programId, program.coder).parseLogs(transaction.meta.logMessages)];
+            const createdEvent = events.find((event) => event.name === "poolCreatedEvent");
+            assert.ok(createdEvent, "PoolCreatedEvent not emitted");
+            assert.ok(createdEvent.data.pool.equals(poolPda), "Event pool mismatch");
+            assert.ok(createdEvent.data.creator.equals(intializer.publicKey), "Event creator mismatch");
+            assert.equal(createdEvent.data.lpAmount.toNumber(), expectedLp - minimumLiquidity, "Event LP amount mismatch");
+            // Equal seeds of equal-decimal mints start the pool at a price of 1 (18-decimal fixed point)
+            assert.equal(createdEvent.data.initialPrice.toString(), "1000000000000000000", "Event starting price mismatch");
         });
 
         it("Fails to initialize an already intialized pool", async () => {
@@ -308,15 +558,23 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .initializePool()
+                    .initializePool(poolFeeBps, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, poolSeedAmount, poolSeedAmount)
                     .accounts({
+                        config: configPda,
                         initializer: intializer.publicKey,
                         tokenAMint: mintAKey,
                         tokenBMint: mintBKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        ...(await poolIndexAccountsFor(mintAKey, mintBKey)),
+                        initializerTokenAAccount: intializerAtaFor(mintAKey),
+                        initializerTokenBAccount: intializerAtaFor(mintBKey),
+                        initializerLpTokenAccount: intializerAtaFor(lpMintPda),
+                        lockedLpAccount: lockedLpFor(lpMintPda, poolAuthorityPda),
                         poolAuthority: poolAuthorityPda,
                         tokenAVault: poolTokenAVault,
                         tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
                         tokenProgram: TOKEN_PROGRAM_ID,
                         associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
                         systemProgram: SystemProgram.programId,
@@ -329,6 +587,244 @@ describe("web3-for-dummies", () => {
                 assert.include(e.toString(), "Simulation failed", "Expected initialization error");
             }
         });
+
+        it("Fails to initialize a pool with a fee of 100% or more", async () => {
+            const mintX = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const mintY = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const [mintXKey, mintYKey] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            const pool = poolFor(mintXKey, mintYKey, 10_000);
+            const [lpMint] = PublicKey.findProgramAddressSync([Buffer.from("lp_mint"), pool.toBuffer()], program.programId);
+            await fundIntializer(mintXKey, 0);
+            await fundIntializer(mintYKey, 0);
+
+            try {
+                await program.methods
+                    .initializePool(10_000, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, poolSeedAmount, poolSeedAmount)
+                    .accounts({
+                        config: configPda,
+                        initializer: intializer.publicKey,
+                        tokenAMint: mintXKey,
+                        tokenBMint: mintYKey,
+                        pool,
+                        oracle: oracleFor(pool),
+                        ...(await poolIndexAccountsFor(mintXKey, mintYKey)),
+                        initializerTokenAAccount: intializerAtaFor(mintXKey),
+                        initializerTokenBAccount: intializerAtaFor(mintYKey),
+                        initializerLpTokenAccount: intializerAtaFor(lpMint),
+                        lockedLpAccount: lockedLpFor(lpMint, authorityFor(pool)),
+                        poolAuthority: authorityFor(pool),
+                        tokenAVault: await getAssociatedTokenAddress(mintXKey, authorityFor(pool), true),
+                        tokenBVault: await getAssociatedTokenAddress(mintYKey, authorityFor(pool), true),
+                        lpMint,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed due to an invalid fee");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidFee", "Expected InvalidFee error");
+            }
+        });
+
+        it("Fails to initialize a pool with a fee outside the config's fee tiers", async () => {
+            const mintX = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const mintY = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const [mintXKey, mintYKey] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            const pool = poolFor(mintXKey, mintYKey, 25);
+            const [lpMint] = PublicKey.findProgramAddressSync([Buffer.from("lp_mint"), pool.toBuffer()], program.programId);
+            await fundIntializer(mintXKey, 0);
+            await fundIntializer(mintYKey, 0);
+
+            try {
+                await program.methods
+                    .initializePool(25, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, poolSeedAmount, poolSeedAmount)
+                    .accounts({
+                        config: configPda,
+                        initializer: intializer.publicKey,
+                        tokenAMint: mintXKey,
+                        tokenBMint: mintYKey,
+                        pool,
+                        oracle: oracleFor(pool),
+                        ...(await poolIndexAccountsFor(mintXKey, mintYKey)),
+                        initializerTokenAAccount: intializerAtaFor(mintXKey),
+                        initializerTokenBAccount: intializerAtaFor(mintYKey),
+                        initializerLpTokenAccount: intializerAtaFor(lpMint),
+                        lockedLpAccount: lockedLpFor(lpMint, authorityFor(pool)),
+                        poolAuthority: authorityFor(pool),
+                        tokenAVault: await getAssociatedTokenAddress(mintXKey, authorityFor(pool), true),
+                        tokenBVault: await getAssociatedTokenAddress(mintYKey, authorityFor(pool), true),
+                        lpMint,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed due to a fee outside the tiers");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidFeeTier", "Expected InvalidFeeTier error");
+            }
+        });
+
+        describe("mint order", () => {
+            let x: { mint: PublicKey, ata: PublicKey };
+            let y: { mint: PublicKey, ata: PublicKey };
+            let lowMint: PublicKey;
+            let highMint: PublicKey;
+            let canonicalPool: PublicKey;
+
+            before(async () => {
+                x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+                y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+                [lowMint, highMint] = [x.mint, y.mint].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+                canonicalPool = poolFor(lowMint, highMint);
+            });
+
+            // Initializes a pool passing `first` as Token A and `second` as Token B, without sorting them
+            const initializeInOrder = async (first: PublicKey, second: PublicKey, amountFirst: BN, amountSecond: BN) => {
+                await fundIntializer(first, amountFirst.toString());
+                await fundIntializer(second, amountSecond.toString());
+                const [sortedFirst, sortedSecond] = [first, second].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+                const pool = poolFor(sortedFirst, sortedSecond);
+                const [lpMint] = PublicKey.findProgramAddressSync([Buffer.from("lp_mint"), pool.toBuffer()], program.programId);
+                return program.methods
+                    .initializePool(poolFeeBps, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, amountFirst, amountSecond)
+                    .accounts({
+                        config: configPda,
+                        initializer: intializer.publicKey,
+                        tokenAMint: first,
+                        tokenBMint: second,
+                        pool,
+                        oracle: oracleFor(pool),
+                        ...(await poolIndexAccountsFor(first, second)),
+                        initializerTokenAAccount: intializerAtaFor(first),
+                        initializerTokenBAccount: intializerAtaFor(second),
+                        initializerLpTokenAccount: intializerAtaFor(lpMint),
+                        lockedLpAccount: lockedLpFor(lpMint, authorityFor(pool)),
+                        poolAuthority: authorityFor(pool),
+                        tokenAVault: getAssociatedTokenAddressSync(first, authorityFor(pool), true),
+                        tokenBVault: getAssociatedTokenAddressSync(second, authorityFor(pool), true),
+                        lpMint,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([intializer])
+                    .rpc();
+            };
+
+            it("Creates the canonical pool when the mints are passed in reverse order", async () => {
+                const amountHigh = new BN(1_000 * (10 ** decimals));
+                const amountLow = new BN(2_000 * (10 ** decimals));
+                await initializeInOrder(highMint, lowMint, amountHigh, amountLow);
+
+                const poolAccount = await program.account.liquidityPool.fetch(canonicalPool);
+                const lowVault = getAssociatedTokenAddressSync(lowMint, authorityFor(canonicalPool), true);
+                const highVault = getAssociatedTokenAddressSync(highMint, authorityFor(canonicalPool), true);
+                assert.ok(poolAccount.tokenAMint.equals(lowMint), "Pool mint A should be the smaller mint");
+                assert.ok(poolAccount.tokenBMint.equals(highMint), "Pool mint B should be the larger mint");
+                assert.ok(poolAccount.tokenAVault.equals(lowVault), "Pool vault A mismatch");
+                assert.ok(poolAccount.tokenBVault.equals(highVault), "Pool vault B mismatch");
+                // The initial amounts follow the order the mints were passed in
+                assert.equal(await getTokenBalance(lowVault), amountLow.toNumber(), "Smaller mint seed mismatch");
+                assert.equal(await getTokenBalance(highVault), amountHigh.toNumber(), "Larger mint seed mismatch");
+
+                // The pool is usable with the sorted accounts swaps derive
+                const [aliceLow, aliceHigh] = lowMint.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+                const aliceHigh_before = await getTokenBalance(aliceHigh);
+                await program.methods
+                    .swap(new BN(1 * (10 ** decimals)), new BN(1), null)
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        pool: canonicalPool,
+                        oracle: oracleFor(canonicalPool),
+                        config: configPda,
+                        poolAuthority: authorityFor(canonicalPool),
+                        sourceMint: lowMint,
+                        destinationMint: highMint,
+                        userSourceTokenAccount: aliceLow,
+                        userDestinationTokenAccount: aliceHigh,
+                        tokenAVault: lowVault,
+                        tokenBVault: highVault,
+                        tokenProgram: TOKEN_PROGRAM_ID
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.ok(await getTokenBalance(aliceHigh) > aliceHigh_before, "Swap should pay out");
+            });
+
+            it("Fails to create a second pool for the pair in the sorted order", async () => {
+                const amount = new BN(1_000 * (10 ** decimals));
+                try {
+                    await initializeInOrder(lowMint, highMint, amount, amount);
+                    assert.fail("Should have failed to create a second pool for the pair");
+                } catch (e) {
+                    assert.include(e.toString(), "Simulation failed", "Expected initialization error");
+                }
+            });
+
+            it("Fails to create a pool for a mint paired with itself", async () => {
+                const amount = new BN(1_000 * (10 ** decimals));
+                try {
+                    await initializeInOrder(lowMint, lowMint, amount, amount);
+                    assert.fail("Should have failed due to identical mints");
+                } catch (e) {
+                    assert.include(e.toString(), "InvalidMint", "Expected InvalidMint error");
+                }
+            });
+        });
+
+        it("Fails to create a pool without seed liquidity", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            try {
+                await createSeededPool(x.mint, y.mint, new BN(0));
+                assert.fail("Should have failed due to a missing seed deposit");
+            } catch (e) {
+                assert.include(e.toString(), "ZeroAmount", "Expected ZeroAmount error");
+            }
+        });
+
+        it("Creates separate pools for different fee tiers of the same pair", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const amount = new BN(1000 * (10 ** decimals));
+            const lowFeePool = await createSeededPool(x.mint, y.mint, amount, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, 5);
+            const highFeePool = await createSeededPool(x.mint, y.mint, amount, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, 100);
+
+            assert.ok(!lowFeePool.pool.equals(highFeePool.pool), "Fee tiers should map to different pools");
+            assert.ok(!lowFeePool.vaultA.equals(highFeePool.vaultA), "Fee tiers should have separate vaults");
+
+            const lowFeeAccount = await program.account.liquidityPool.fetch(lowFeePool.pool);
+            const highFeeAccount = await program.account.liquidityPool.fetch(highFeePool.pool);
+            assert.equal(lowFeeAccount.feeTierBps, 5, "Low fee tier mismatch");
+            assert.equal(highFeeAccount.feeTierBps, 100, "High fee tier mismatch");
+            assert.equal(await getTokenBalance(lowFeePool.vaultA), amount.toNumber(), "Low fee pool vault A mismatch");
+            assert.equal(await getTokenBalance(highFeePool.vaultA), amount.toNumber(), "High fee pool vault A mismatch");
+        });
+
+        it("Lists every pool for a mint in the mint's pool index", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const z = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const amount = new BN(1000 * (10 ** decimals));
+            const poolXY = await createSeededPool(x.mint, y.mint, amount);
+            const poolXZ = await createSeededPool(x.mint, z.mint, amount);
+
+            const indexX = await program.account.mintPoolIndex.fetch(poolIndexFor(x.mint));
+            const pageX = await program.account.mintPoolIndexPage.fetch(poolIndexPageFor(x.mint, 0));
+            const pageY = await program.account.mintPoolIndexPage.fetch(poolIndexPageFor(y.mint, 0));
+            assert.ok(indexX.mint.equals(x.mint), "Index mint mismatch");
+            assert.equal(indexX.poolCount, 2, "Mint X pool count mismatch");
+            assert.equal(pageX.page, 0, "Page number mismatch");
+            assert.deepEqual(pageX.pools.map((p) => p.toBase58()), [poolXY.pool.toBase58(), poolXZ.pool.toBase58()], "Mint X pools mismatch");
+            assert.deepEqual(pageY.pools.map((p) => p.toBase58()), [poolXY.pool.toBase58()], "Mint Y pools mismatch");
+
+            const mainPage = await program.account.mintPoolIndexPage.fetch(poolIndexPageFor(tokenAMint, 0));
+            assert.ok(mainPage.pools.some((p) => p.equals(poolPda)), "Main pool missing from its mint's index");
+        });
     });
 
     describe("add_liquidity", () => {
@@ -345,15 +841,23 @@ describe("web3-for-dummies", () => {
                 const [mintAkey, mintBKey] = getSortedMints();
                 try {
                     await program.methods
-                        .initializePool()
+                        .initializePool(poolFeeBps, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, poolSeedAmount, poolSeedAmount)
                         .accounts({
+                            config: configPda,
                             initializer: intializer.publicKey,
                             tokenAMint: mintAkey,
                             tokenBMint: mintBKey,
                             pool: poolPda,
+                            oracle: oracleFor(poolPda),
+                            ...(await poolIndexAccountsFor(mintAkey, mintBKey)),
+                            initializerTokenAAccount: intializerAtaFor(mintAkey),
+                            initializerTokenBAccount: intializerAtaFor(mintBKey),
+                            initializerLpTokenAccount: intializerAtaFor(lpMintPda),
+                            lockedLpAccount: lockedLpFor(lpMintPda, poolAuthorityPda),
                             poolAuthority: poolAuthorityPda,
                             tokenAVault: poolTokenAVault,
                             tokenBVault: poolTokenBVault,
+                            lpMint: lpMintPda,
                             tokenProgram: TOKEN_PROGRAM_ID,
                             associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
                             systemProgram: SystemProgram.programId,
@@ -366,24 +870,28 @@ describe("web3-for-dummies", () => {
                     console.log("Could not initialize pool: " + e.toString().slice(0, 100));
                 }
             }
+            await ensureAliceLpAccount();
         });
 
-        it("Adds initial Liquidity successfully", async () => {
+        it("Adds liquidity to the seeded pool", async () => {
             const aliceA_before = await getTokenBalance(aliceTokenAAccount);
             const aliceB_before = await getTokenBalance(aliceTokenBAccount)
 
             const poolAccounts = await getPoolAccounts();
             const vaultA_before = await getTokenBalance(poolAccounts.tokenAVault)
             const vaultB_before = await getTokenBalance(poolAccounts.tokenBVault);
+            const aliceLp_before = await getTokenBalance(aliceLpTokenAccount);
 
             try {
                 const poolAccount = await program.account.liquidityPool.fetch(poolPda);
 
                 await program.methods
-                    .addLiquidity(initialLiquidityA, initialLiquidityB)
+                    .addLiquidity(initialLiquidityA, initialLiquidityB, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
                         poolAuthority: poolAuthorityPda,
                         // Use the mints from the pool account, not the original mint variables
                         tokenAMint: poolAccount.tokenAMint,
@@ -392,6 +900,8 @@ describe("web3-for-dummies", () => {
                         userTokenBAccount: aliceTokenBAccount,
                         tokenAVault: poolTokenAVault,
                         tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: aliceLpTokenAccount,
                         tokenProgram: TOKEN_PROGRAM_ID,
                     } as any)
                     .signers([alice])
@@ -405,6 +915,11 @@ describe("web3-for-dummies", () => {
                 assert.equal(aliceB_before - aliceB_after, initialLiquidityB.toNumber(), "Alice B balance change mismatch");
                 assert.equal(vaultA_after - vaultA_before, initialLiquidityA.toNumber(), "Vault A balance change mismatch");
                 assert.equal(vaultB_after - vaultB_before, initialLiquidityB.toNumber(), "Vault B balance change mismatch");
+
+                // The pool was seeded 1:1 with sqrt(a * b) LP shares, so a 1:1 deposit mints the same
+                const aliceLp_after = await getTokenBalance(aliceLpTokenAccount);
+                const expectedLp = Math.floor(Math.sqrt(initialLiquidityA.toNumber() * initialLiquidityB.toNumber()));
+                assert.equal(aliceLp_after - aliceLp_before, expectedLp, "Alice LP balance change mismatch");
             } catch (e) {
                 console.log("Skipping add_liquidity test - pool not initialized");
                 return;
@@ -427,12 +942,16 @@ describe("web3-for-dummies", () => {
             const aliceB_before = await getTokenBalance(aliceTokenBAccount)
             const vaultA_before = await getTokenBalance(poolTokenAVault)
             const vaultB_before = await getTokenBalance(poolTokenBVault)
+            const aliceLp_before = await getTokenBalance(aliceLpTokenAccount)
+            const lpSupply_before = Number((await getMint(provider.connection, lpMintPda)).supply)
 
             await program.methods
-                .addLiquidity(subsequentLiquidityA, subsequentLiquidityB)
+                .addLiquidity(subsequentLiquidityA, subsequentLiquidityB, null)
                 .accounts({
                     userAuthority: alice.publicKey,
                     pool: poolPda,
+                    oracle: oracleFor(poolPda),
+                    config: configPda,
                     poolAuthority: poolAuthorityPda,
                     tokenAMint: poolAccounts.tokenAMint,
                     tokenBMint: poolAccounts.tokenBMint,
@@ -440,6 +959,8 @@ describe("web3-for-dummies", () => {
                     userTokenBAccount: aliceTokenBAccount,
                     tokenAVault: poolAccounts.tokenAVault,
                     tokenBVault: poolAccounts.tokenBVault,
+                    lpMint: lpMintPda,
+                    userLpTokenAccount: aliceLpTokenAccount,
                     tokenProgram: TOKEN_PROGRAM_ID,
                 } as any)
                 .signers([alice])
@@ -455,15 +976,25 @@ describe("web3-for-dummies", () => {
             assert.equal(vaultA_after - vaultA_before, subsequentLiquidityA.toNumber(), "Vault A balance change mismatch (subsequent)");
             assert.equal(vaultB_after - vaultB_before, subsequentLiquidityB.toNumber(), "Vault B balance change mismatch (subsequent)");
 
+            // Subsequent deposits mint shares proportional to the existing supply
+            const aliceLp_after = await getTokenBalance(aliceLpTokenAccount)
+            const expectedLp = Math.min(
+                Math.floor(subsequentLiquidityA.toNumber() * lpSupply_before / vaultA_before),
+                Math.floor(subsequentLiquidityB.toNumber() * lpSupply_before / vaultB_before),
+            );
+            assert.equal(aliceLp_after - aliceLp_before, expectedLp, "Alice LP balance change mismatch (subsequent)");
+
         });
 
         it("Fails to add liquidity with zero amount", async () => {
             try {
                 await program.methods
-                    .addLiquidity(new BN(0), new BN(10 * (10 ** decimals)))
+                    .addLiquidity(new BN(0), new BN(10 * (10 ** decimals)), null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
                         poolAuthority: poolAuthorityPda,
                         tokenAMint: tokenAMint,
                         tokenBMint: tokenBMint,
@@ -471,6 +1002,8 @@ describe("web3-for-dummies", () => {
                         userTokenBAccount: aliceTokenBAccount,
                         tokenAVault: poolTokenAVault,
                         tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: aliceLpTokenAccount,
                         tokenProgram: TOKEN_PROGRAM_ID,
                     } as any)
                     .signers([alice])
@@ -482,16 +1015,49 @@ describe("web3-for-dummies", () => {
             }
         });
 
+        it("Fails to add liquidity after the deadline", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const now = Math.floor(Date.now() / 1000);
+
+            try {
+                await program.methods
+                    .addLiquidity(new BN(10 * (10 ** decimals)), new BN(10 * (10 ** decimals)), new BN(now - 600))
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
+                        poolAuthority: poolAuthorityPda,
+                        tokenAMint: poolAccounts.tokenAMint,
+                        tokenBMint: poolAccounts.tokenBMint,
+                        userTokenAAccount: poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount,
+                        userTokenBAccount: poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount,
+                        tokenAVault: poolAccounts.tokenAVault,
+                        tokenBVault: poolAccounts.tokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: aliceLpTokenAccount,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an expired deadline");
+            } catch (e) {
+                assert.include(e.toString(), "Expired", "Expected Expired error");
+            }
+        });
+
         it("Fails to add disproportionate liquidity", async () => {
             const disproportionateLiquidityA = new BN(10 * (10 ** decimals));
             const disproportionateLiquidityB = new BN(50 * (10 ** decimals))
 
             try {
                 await program.methods
-                    .addLiquidity(disproportionateLiquidityA, disproportionateLiquidityB)
+                    .addLiquidity(disproportionateLiquidityA, disproportionateLiquidityB, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
                         poolAuthority: poolAuthorityPda,
                         tokenAMint: tokenAMint,
                         tokenBMint: tokenBMint,
@@ -499,6 +1065,8 @@ describe("web3-for-dummies", () => {
                         userTokenBAccount: aliceTokenBAccount,
                         tokenAVault: poolTokenAVault,
                         tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: aliceLpTokenAccount,
                         tokenProgram: TOKEN_PROGRAM_ID
                     } as any)
                     .signers([alice])
@@ -536,15 +1104,23 @@ describe("web3-for-dummies", () => {
 
                     try {
                         await program.methods
-                            .initializePool()
+                            .initializePool(poolFeeBps, constantProductCurve, new BN(0), 0, fixedFee, poolMaxPriceImpactBps, poolSeedAmount, poolSeedAmount)
                             .accounts({
+                                config: configPda,
                                 initializer: intializer.publicKey,
                                 tokenAMint: mintAkey,
                                 tokenBMint: mintBKey,
                                 pool: poolPda,
+                                oracle: oracleFor(poolPda),
+                                ...(await poolIndexAccountsFor(mintAkey, mintBKey)),
+                                initializerTokenAAccount: intializerAtaFor(mintAkey),
+                                initializerTokenBAccount: intializerAtaFor(mintBKey),
+                                initializerLpTokenAccount: intializerAtaFor(lpMintPda),
+                                lockedLpAccount: lockedLpFor(lpMintPda, poolAuthorityPda),
                                 poolAuthority: poolAuthorityPda,
                                 tokenAVault: poolTokenAVault,
                                 tokenBVault: poolTokenBVault,
+                                lpMint: lpMintPda,
                                 tokenProgram: TOKEN_PROGRAM_ID,
                                 associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
                                 systemProgram: SystemProgram.programId,
@@ -566,15 +1142,19 @@ describe("web3-for-dummies", () => {
                     return;
                 }
 
+                await ensureAliceLpAccount();
+
                 // 4. Add significant liquidity
                 const liquidityAmount = new BN(1000 * (10 ** decimals));
 
                 try {
                     await program.methods
-                        .addLiquidity(liquidityAmount, liquidityAmount)
+                        .addLiquidity(liquidityAmount, liquidityAmount, null)
                         .accounts({
                             userAuthority: alice.publicKey,
                             pool: poolPda,
+                            oracle: oracleFor(poolPda),
+                            config: configPda,
                             poolAuthority: poolAuthorityPda,
                             tokenAMint: poolAccounts.tokenAMint,
                             tokenBMint: poolAccounts.tokenBMint,
@@ -582,6 +1162,8 @@ describe("web3-for-dummies", () => {
                             userTokenBAccount: aliceTokenBAccount,
                             tokenAVault: poolAccounts.tokenAVault,
                             tokenBVault: poolAccounts.tokenBVault,
+                            lpMint: lpMintPda,
+                            userLpTokenAccount: aliceLpTokenAccount,
                             tokenProgram: TOKEN_PROGRAM_ID,
                         } as any)
                         .signers([alice])
@@ -604,14 +1186,12 @@ describe("web3-for-dummies", () => {
                 return new BN(0);
             }
 
-            const feeNumerator = BigInt(3);
-            const feeDenominator = BigInt(1000);
+            const feeNumerator = BigInt(poolFeeNumerator.toString());
+            const feeDenominator = BigInt(poolFeeDenominator.toString());
             const amountInAfterFee = (amountInU128 * (feeDenominator - feeNumerator)) / feeDenominator;
 
-            const constantProduct = reserveInU128 * reserveOutU128;
             const newReserveIn = reserveInU128 + amountInAfterFee;
-            const newReserveOut = constantProduct / newReserveIn;
-            const amountOutU128 = reserveOutU128 - newReserveOut;
+            const amountOutU128 = (reserveOutU128 * amountInAfterFee) / newReserveIn;
 
             return new BN(amountOutU128.toString());
         };
@@ -639,10 +1219,12 @@ describe("web3-for-dummies", () => {
             assert.ok(expectedBOut.gt(new BN(0)), "Expected output should be positive");
 
             await program.methods
-                .swap(swapAmountA, expectedBOut.muln(98).divn(100)) // Swap A, min B out (use expectedBOut)
+                .swap(swapAmountA, expectedBOut.muln(98).divn(100), null) // Swap A, min B out (use expectedBOut)
                 .accounts({
                     userAuthority: alice.publicKey,
                     pool: poolPda,
+                    oracle: oracleFor(poolPda),
+                    config: configPda,
                     poolAuthority: poolAuthorityPda,
                     // Corrected mints for A -> B swap
                     sourceMint: poolAccounts.tokenAMint,      // Source is A
@@ -692,10 +1274,12 @@ describe("web3-for-dummies", () => {
             assert.ok(expectedAOut.gt(new BN(0)), "Expected output should be positive");
 
             await program.methods
-                .swap(swapAmountB, expectedAOut.muln(98).divn(100)) // Swap B, min A out
+                .swap(swapAmountB, expectedAOut.muln(98).divn(100), null) // Swap B, min A out
                 .accounts({
                     userAuthority: alice.publicKey,
                     pool: poolPda,
+                    oracle: oracleFor(poolPda),
+                    config: configPda,
                     poolAuthority: poolAuthorityPda,
                     // Corrected mints for B -> A swap
                     sourceMint: poolAccounts.tokenBMint,      // Source is B
@@ -726,14 +1310,100 @@ describe("web3-for-dummies", () => {
 
         });
 
+        it("Grows the constant product k after each swap", async () => {
+            const poolAccounts = await getPoolAccounts();
+            if (!poolAccounts) {
+                console.log("Skipping test - pool not initialized");
+                return;
+            }
+            const aliceSourceA = poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount;
+            const aliceSourceB = poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount;
+
+            const getK = async (): Promise<bigint> => {
+                const vaultA = BigInt(await getTokenBalance(poolAccounts.tokenAVault));
+                const vaultB = BigInt(await getTokenBalance(poolAccounts.tokenBVault));
+                return vaultA * vaultB;
+            };
+
+            const directions = [
+                { sourceMint: poolAccounts.tokenAMint, destinationMint: poolAccounts.tokenBMint, source: aliceSourceA, destination: aliceSourceB },
+                { sourceMint: poolAccounts.tokenBMint, destinationMint: poolAccounts.tokenAMint, source: aliceSourceB, destination: aliceSourceA },
+            ];
+
+            for (let i = 0; i < 4; i++) {
+                const { sourceMint, destinationMint, source, destination } = directions[i % 2];
+                const kBefore = await getK();
+
+                await program.methods
+                    .swap(new BN(5 * (10 ** decimals)), new BN(1), null)
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
+                        poolAuthority: poolAuthorityPda,
+                        sourceMint,
+                        destinationMint,
+                        userSourceTokenAccount: source,
+                        userDestinationTokenAccount: destination,
+                        tokenAVault: poolAccounts.tokenAVault,
+                        tokenBVault: poolAccounts.tokenBVault,
+                        tokenProgram: TOKEN_PROGRAM_ID
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+
+                const kAfter = await getK();
+                assert.ok(kAfter > kBefore, `k did not grow on swap ${i}: ${kBefore} -> ${kAfter}`);
+            }
+        });
+
+        it("Swaps before the deadline and rejects swaps after it", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const now = Math.floor(Date.now() / 1000);
+            const accounts = {
+                userAuthority: alice.publicKey,
+                pool: poolPda,
+                oracle: oracleFor(poolPda),
+                config: configPda,
+                poolAuthority: poolAuthorityPda,
+                sourceMint: poolAccounts.tokenAMint,
+                destinationMint: poolAccounts.tokenBMint,
+                userSourceTokenAccount: poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount,
+                userDestinationTokenAccount: poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount,
+                tokenAVault: poolAccounts.tokenAVault,
+                tokenBVault: poolAccounts.tokenBVault,
+                tokenProgram: TOKEN_PROGRAM_ID
+            };
+
+            await program.methods
+                .swap(new BN(1 * (10 ** decimals)), new BN(1), new BN(now + 600))
+                .accounts(accounts as any)
+                .signers([alice])
+                .rpc();
+
+            try {
+                await program.methods
+                    .swap(new BN(1 * (10 ** decimals)), new BN(1), new BN(now - 600))
+                    .accounts(accounts as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an expired deadline");
+            } catch (error) {
+                assert.include(error.toString(), "Expired", "Expected Expired error");
+            }
+        });
+
         it("Fails swap with zero amount in", async () => {
             const poolAccounts = await getPoolAccounts();
             try {
                 await program.methods
-                    .swap(new BN(0), mintAmountBOut)
+                    .swap(new BN(0), mintAmountBOut, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -762,10 +1432,12 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .swap(swapAmountA, tooHighMInAmountOut)
+                    .swap(swapAmountA, tooHighMInAmountOut, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -788,10 +1460,12 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .swap(swapAmountA, swapAmountB)
+                    .swap(swapAmountA, swapAmountB, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -819,10 +1493,12 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .swap(swapAmountA, swapAmountB)
+                    .swap(swapAmountA, swapAmountB, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -850,10 +1526,12 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .swap(swapAmountA, mintAmountBOut)
+                    .swap(swapAmountA, mintAmountBOut, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        oracle: oracleFor(poolPda),
+                        config: configPda,
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -871,4 +1549,1853 @@ describe("web3-for-dummies", () => {
             }
         });
     });
+
+    describe("swap_exact_out", () => {
+        const ceilDiv = (a: bigint, b: bigint): bigint => (a + b - BigInt(1)) / b;
+
+        const calculateExpectedIn = (amountOut: bigint, reserveIn: bigint, reserveOut: bigint): bigint => {
+            const feeNumerator = BigInt(poolFeeNumerator.toString());
+            const feeDenominator = BigInt(poolFeeDenominator.toString());
+            const amountInAfterFee = ceilDiv(reserveIn * amountOut, reserveOut - amountOut);
+            return ceilDiv(amountInAfterFee * feeDenominator, feeDenominator - feeNumerator);
+        };
+
+        const swapAccounts = async () => {
+            const poolAccounts = await getPoolAccounts();
+            return {
+                userAuthority: alice.publicKey,
+                pool: poolPda,
+                oracle: oracleFor(poolPda),
+                config: configPda,
+                poolAuthority: poolAuthorityPda,
+                sourceMint: poolAccounts.tokenAMint,
+                destinationMint: poolAccounts.tokenBMint,
+                userSourceTokenAccount: poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount,
+                userDestinationTokenAccount: poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount,
+                tokenAVault: poolAccounts.tokenAVault,
+                tokenBVault: poolAccounts.tokenBVault,
+                tokenProgram: TOKEN_PROGRAM_ID
+            };
+        };
+
+        it("Receives exactly the requested output amount", async () => {
+            const accounts = await swapAccounts();
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            const reserveIn = BigInt(await getTokenBalance(accounts.tokenAVault)) - BigInt(poolAccount.protocolFeesA.toString());
+            const reserveOut = BigInt(await getTokenBalance(accounts.tokenBVault)) - BigInt(poolAccount.protocolFeesB.toString());
+
+            const amountOut = BigInt(3 * (10 ** decimals));
+            const expectedIn = calculateExpectedIn(amountOut, reserveIn, reserveOut);
+
+            const source_before = await getTokenBalance(accounts.userSourceTokenAccount);
+            const destination_before = await getTokenBalance(accounts.userDestinationTokenAccount);
+
+            await program.methods
+                .swapExactOut(new BN(amountOut.toString()), new BN(expectedIn.toString()), null)
+                .accounts(accounts as any)
+                .signers([alice])
+                .rpc();
+
+            const source_after = await getTokenBalance(accounts.userSourceTokenAccount);
+            const destination_after = await getTokenBalance(accounts.userDestinationTokenAccount);
+
+            assert.equal(destination_after - destination_before, Number(amountOut), "Output amount mismatch");
+            assert.equal(source_before - source_after, Number(expectedIn), "Input amount mismatch");
+        });
+
+        it("Fails when the required input exceeds max_amount_in", async () => {
+            const accounts = await swapAccounts();
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            const reserveIn = BigInt(await getTokenBalance(accounts.tokenAVault)) - BigInt(poolAccount.protocolFeesA.toString());
+            const reserveOut = BigInt(await getTokenBalance(accounts.tokenBVault)) - BigInt(poolAccount.protocolFeesB.toString());
+
+            const amountOut = BigInt(3 * (10 ** decimals));
+            const expectedIn = calculateExpectedIn(amountOut, reserveIn, reserveOut);
+
+            try {
+                await program.methods
+                    .swapExactOut(new BN(amountOut.toString()), new BN((expectedIn - BigInt(1)).toString()), null)
+                    .accounts(accounts as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to slippage");
+            } catch (e) {
+                assert.include(e.toString(), "SlippageExceeded", "Expected SlippageExceeded error");
+            }
+        });
+
+        it("Fails swap_exact_out with zero amount out", async () => {
+            try {
+                await program.methods
+                    .swapExactOut(new BN(0), new BN(10 * (10 ** decimals)), null)
+                    .accounts(await swapAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to zero amount out");
+            } catch (e) {
+                assert.include(e.toString(), "ZeroAmount", "Expected ZeroAmount error");
+            }
+        });
+
+        it("Fails swap_exact_out after the deadline", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            try {
+                await program.methods
+                    .swapExactOut(new BN(1 * (10 ** decimals)), new BN(10 * (10 ** decimals)), new BN(now - 600))
+                    .accounts(await swapAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an expired deadline");
+            } catch (e) {
+                assert.include(e.toString(), "Expired", "Expected Expired error");
+            }
+        });
+    });
+
+    describe("route_swap", () => {
+        const hopLiquidity = new BN(1000 * (10 ** decimals));
+        let tokenCMint: PublicKey;
+        let aliceTokenCAccount: PublicKey;
+        let poolAB: Awaited<ReturnType<typeof createSeededPool>>;
+        let poolBC: Awaited<ReturnType<typeof createSeededPool>>;
+        let routeSource: { mint: PublicKey, ata: PublicKey };
+        let routeMiddle: { mint: PublicKey, ata: PublicKey };
+
+        before(async () => {
+            // Two fresh pools X/Y and Y/Z so the route has no direct X/Z pool
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const z = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            tokenCMint = z.mint;
+            aliceTokenCAccount = z.ata;
+
+            poolAB = await createSeededPool(x.mint, y.mint, hopLiquidity);
+            poolBC = await createSeededPool(y.mint, z.mint, hopLiquidity);
+
+            routeSource = x;
+            routeMiddle = y;
+        });
+
+        const hopAccounts = (pool: Awaited<ReturnType<typeof createSeededPool>>) => [
+            { pubkey: pool.pool, isSigner: false, isWritable: true },
+            { pubkey: pool.poolAuthority, isSigner: false, isWritable: false },
+            { pubkey: pool.vaultA, isSigner: false, isWritable: true },
+            { pubkey: pool.vaultB, isSigner: false, isWritable: true },
+            { pubkey: oracleFor(pool.pool), isSigner: false, isWritable: true },
+        ];
+
+        const quote = (amountIn: bigint, reserveIn: bigint, reserveOut: bigint): bigint => {
+            const feeNumerator = BigInt(poolFeeNumerator.toString());
+            const feeDenominator = BigInt(poolFeeDenominator.toString());
+            const amountInAfterFee = (amountIn * (feeDenominator - feeNumerator)) / feeDenominator;
+            return (reserveOut * amountInAfterFee) / (reserveIn + amountInAfterFee);
+        };
+
+        const routeAccounts = () => ({
+            userAuthority: alice.publicKey,
+            config: configPda,
+            sourceMint: routeSource.mint,
+            destinationMint: tokenCMint,
+            userSourceTokenAccount: routeSource.ata,
+            userDestinationTokenAccount: aliceTokenCAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        });
+
+        it("Swaps through two pools in one instruction", async () => {
+            const amountIn = BigInt(10 * (10 ** decimals));
+            // Both pools start balanced, so each hop sees equal reserves
+            const middleOut = quote(amountIn, BigInt(hopLiquidity.toString()), BigInt(hopLiquidity.toString()));
+            const expectedOut = quote(middleOut, BigInt(hopLiquidity.toString()), BigInt(hopLiquidity.toString()));
+
+            const source_before = await getTokenBalance(routeSource.ata);
+            const middle_before = await getTokenBalance(routeMiddle.ata);
+            const destination_before = await getTokenBalance(aliceTokenCAccount);
+
+            await program.methods
+                .routeSwap(new BN(amountIn.toString()), new BN(expectedOut.toString()), 2, null)
+                .accounts(routeAccounts() as any)
+                .remainingAccounts([
+                    ...hopAccounts(poolAB),
+                    ...hopAccounts(poolBC),
+                    { pubkey: routeMiddle.mint, isSigner: false, isWritable: false },
+                ])
+                .signers([alice])
+                .rpc();
+
+            assert.equal(source_before - await getTokenBalance(routeSource.ata), Number(amountIn), "Source amount mismatch");
+            assert.equal(await getTokenBalance(routeMiddle.ata), middle_before, "Intermediate token should not touch the user");
+            assert.equal(await getTokenBalance(aliceTokenCAccount) - destination_before, Number(expectedOut), "Output amount mismatch");
+        });
+
+        it("Fails when the final output is below min_amount_out", async () => {
+            try {
+                await program.methods
+                    .routeSwap(new BN(10 * (10 ** decimals)), new BN(10 * (10 ** decimals)), 2, null)
+                    .accounts(routeAccounts() as any)
+                    .remainingAccounts([
+                        ...hopAccounts(poolAB),
+                        ...hopAccounts(poolBC),
+                        { pubkey: routeMiddle.mint, isSigner: false, isWritable: false },
+                    ])
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to slippage");
+            } catch (e) {
+                assert.include(e.toString(), "SlippageExceeded", "Expected SlippageExceeded error");
+            }
+        });
+
+        it("Fails when the route does not end in the destination mint", async () => {
+            try {
+                await program.methods
+                    .routeSwap(new BN(10 * (10 ** decimals)), new BN(1), 1, null)
+                    .accounts(routeAccounts() as any)
+                    .remainingAccounts(hopAccounts(poolAB))
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an incomplete route");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidDestinationMint", "Expected InvalidDestinationMint error");
+            }
+        });
+
+        it("Fails when the hop accounts don't match the hop count", async () => {
+            try {
+                await program.methods
+                    .routeSwap(new BN(10 * (10 ** decimals)), new BN(1), 2, null)
+                    .accounts(routeAccounts() as any)
+                    .remainingAccounts(hopAccounts(poolAB))
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to a malformed route");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidRoute", "Expected InvalidRoute error");
+            }
+        });
+
+        it("Fails route_swap after the deadline", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            try {
+                await program.methods
+                    .routeSwap(new BN(10 * (10 ** decimals)), new BN(1), 2, new BN(now - 600))
+                    .accounts(routeAccounts() as any)
+                    .remainingAccounts([
+                        ...hopAccounts(poolAB),
+                        ...hopAccounts(poolBC),
+                        { pubkey: routeMiddle.mint, isSigner: false, isWritable: false },
+                    ])
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an expired deadline");
+            } catch (e) {
+                assert.include(e.toString(), "Expired", "Expected Expired error");
+            }
+        });
+    });
+
+    describe("zap_in", () => {
+        const zapAccounts = async (userSourceTokenAccount: PublicKey) => {
+            const poolAccounts = await getPoolAccounts();
+            return {
+                userAuthority: alice.publicKey,
+                pool: poolPda,
+                oracle: oracleFor(poolPda),
+                config: configPda,
+                poolAuthority: poolAuthorityPda,
+                tokenAMint: poolAccounts.tokenAMint,
+                tokenBMint: poolAccounts.tokenBMint,
+                userSourceTokenAccount,
+                tokenAVault: poolAccounts.tokenAVault,
+                tokenBVault: poolAccounts.tokenBVault,
+                lpMint: lpMintPda,
+                userLpTokenAccount: aliceLpTokenAccount,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            };
+        };
+
+        it("Deposits a single token and mints LP shares", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const aliceSourceA = poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount;
+            const aliceSourceB = poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount;
+            const amountIn = 10 * (10 ** decimals);
+
+            const aliceA_before = await getTokenBalance(aliceSourceA);
+            const aliceB_before = await getTokenBalance(aliceSourceB);
+            const aliceLp_before = await getTokenBalance(aliceLpTokenAccount);
+            const vaultA_before = await getTokenBalance(poolAccounts.tokenAVault);
+            const vaultB_before = await getTokenBalance(poolAccounts.tokenBVault);
+
+            await program.methods
+                .zapIn(poolAccounts.tokenAMint, new BN(amountIn), new BN(1))
+                .accounts(await zapAccounts(aliceSourceA) as any)
+                .signers([alice])
+                .rpc();
+
+            assert.equal(aliceA_before - await getTokenBalance(aliceSourceA), amountIn, "Alice A balance change mismatch");
+            assert.equal(await getTokenBalance(aliceSourceB), aliceB_before, "Alice B balance should not change");
+            assert.equal(await getTokenBalance(poolAccounts.tokenAVault) - vaultA_before, amountIn, "Vault A balance change mismatch");
+            assert.equal(await getTokenBalance(poolAccounts.tokenBVault), vaultB_before, "Vault B balance should not change");
+            assert.ok(await getTokenBalance(aliceLpTokenAccount) > aliceLp_before, "No LP shares were minted");
+        });
+
+        it("Fails when fewer LP shares than min_lp_out would be minted", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const aliceSourceA = poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount;
+            const lpSupply = Number((await getMint(provider.connection, lpMintPda)).supply);
+
+            try {
+                await program.methods
+                    .zapIn(poolAccounts.tokenAMint, new BN(10 * (10 ** decimals)), new BN(lpSupply))
+                    .accounts(await zapAccounts(aliceSourceA) as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to slippage");
+            } catch (e) {
+                assert.include(e.toString(), "SlippageExceeded", "Expected SlippageExceeded error");
+            }
+        });
+
+        it("Fails with a mint that is not in the pool", async () => {
+            try {
+                await program.methods
+                    .zapIn(simpleTransferMint, new BN(10 * (10 ** decimals)), new BN(1))
+                    .accounts(await zapAccounts(aliceSimpleTokenAccount) as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an invalid mint");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidMint", "Expected InvalidMint error");
+            }
+        });
+    });
+
+    describe("stable_swap", () => {
+        const stableLiquidity = new BN(1000 * (10 ** decimals));
+        const stableAmp = new BN(100);
+        let stablePool: Awaited<ReturnType<typeof createSeededPool>>;
+        let aliceStableA: PublicKey;
+        let aliceStableB: PublicKey;
+
+        before(async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            stablePool = await createSeededPool(x.mint, y.mint, stableLiquidity, stableSwapCurve, stableAmp);
+            [aliceStableA, aliceStableB] = stablePool.mintA.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+        });
+
+        it("Stores the curve type and amplification coefficient", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(stablePool.pool);
+            assert.ok("stableSwap" in poolAccount.curveType, "Curve type mismatch");
+            assert.ok(poolAccount.amp.eq(stableAmp), "Amp mismatch");
+        });
+
+        it("Prices a balanced swap much closer to 1:1 than constant product", async () => {
+            const amountIn = 10 * (10 ** decimals);
+            const aliceB_before = await getTokenBalance(aliceStableB);
+
+            await program.methods
+                .swap(new BN(amountIn), new BN(1), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: stablePool.pool,
+                    oracle: oracleFor(stablePool.pool),
+                    config: configPda,
+                    poolAuthority: stablePool.poolAuthority,
+                    sourceMint: stablePool.mintA,
+                    destinationMint: stablePool.mintB,
+                    userSourceTokenAccount: aliceStableA,
+                    userDestinationTokenAccount: aliceStableB,
+                    tokenAVault: stablePool.vaultA,
+                    tokenBVault: stablePool.vaultB,
+                    tokenProgram: TOKEN_PROGRAM_ID
+                } as any)
+                .signers([alice])
+                .rpc();
+
+            const amountOut = await getTokenBalance(aliceStableB) - aliceB_before;
+            const reserve = BigInt(stableLiquidity.toString());
+            const afterFee = BigInt(amountIn) * BigInt(997) / BigInt(1000);
+            const constantProductOut = Number(reserve * afterFee / (reserve + afterFee));
+
+            assert.ok(amountOut > constantProductOut, `StableSwap output ${amountOut} should beat constant product ${constantProductOut}`);
+            assert.ok(amountOut <= Number(afterFee), "StableSwap output can't exceed the input after fees");
+        });
+
+        it("Reports the price impact against the curve's spot price", async () => {
+            // 15% of the reserves is well past the pool's 10% limit as a share of reserves,
+            // but a StableSwap pool barely moves its price for it
+            const amountIn = 150 * (10 ** decimals);
+            const aliceB_before = await getTokenBalance(aliceStableB);
+
+            const signature = await program.methods
+                .swap(new BN(amountIn), new BN(1), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: stablePool.pool,
+                    oracle: oracleFor(stablePool.pool),
+                    config: configPda,
+                    poolAuthority: stablePool.poolAuthority,
+                    sourceMint: stablePool.mintA,
+                    destinationMint: stablePool.mintB,
+                    userSourceTokenAccount: aliceStableA,
+                    userDestinationTokenAccount: aliceStableB,
+                    tokenAVault: stablePool.vaultA,
+                    tokenBVault: stablePool.vaultB,
+                    tokenProgram: TOKEN_PROGRAM_ID
+                } as any)
+                .signers([alice])
+                .rpc({ commitment: "confirmed" });
+
+            const amountOut = await getTokenBalance(aliceStableB) - aliceB_before;
+            assert.ok(amountOut > amountIn * 0.1, "Swap should pay out more than 10% of the reserves");
+
+            const transaction = await provider.connection.getTransaction(signature, {
+                commitment: "confirmed",
+                maxSupportedTransactionVersion: 0,
+            });
+            const events = [...new anchor.EventParser(program.programId, program.coder).parseLogs(transaction.meta.logMessages)];
+            const swapEvent = events.find((event) => event.name === "swapEvent");
+            assert.ok(swapEvent, "SwapEvent not emitted");
+            assert.equal(swapEvent.data.amountOut.toNumber(), amountOut, "Event amount out mismatch");
+            assert.ok(swapEvent.data.priceImpactBps.toNumber() < 100, `Price impact ${swapEvent.data.priceImpactBps} should stay below 1%`);
+        });
+
+        it("Fails to initialize a StableSwap pool with zero amp", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            try {
+                await createSeededPool(x.mint, y.mint, stableLiquidity, stableSwapCurve, new BN(0));
+                assert.fail("Should have failed due to invalid amp");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidCurveParameters", "Expected InvalidCurveParameters error");
+            }
+        });
+    });
+
+    describe("weighted_pool", () => {
+        const weightedLiquidity = new BN(1000 * (10 ** decimals));
+        // 80/20 pool: Token A (pool order) carries 80% of the weight
+        const tokenAWeightBps = 8000;
+        let weightedPool: Awaited<ReturnType<typeof createSeededPool>>;
+        let aliceWeightedA: PublicKey;
+        let aliceWeightedB: PublicKey;
+
+        before(async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            weightedPool = await createSeededPool(x.mint, y.mint, weightedLiquidity, weightedCurve, new BN(0), tokenAWeightBps);
+            [aliceWeightedA, aliceWeightedB] = weightedPool.mintA.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+        });
+
+        it("Stores the curve type and token weights", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(weightedPool.pool);
+            assert.ok("weighted" in poolAccount.curveType, "Curve type mismatch");
+            assert.equal(poolAccount.tokenAWeightBps, tokenAWeightBps, "Token A weight mismatch");
+            assert.equal(poolAccount.tokenBWeightBps, 10_000 - tokenAWeightBps, "Token B weight mismatch");
+        });
+
+        it("Prices a swap by the weighted product invariant", async () => {
+            const amountIn = 10 * (10 ** decimals);
+            const aliceB_before = await getTokenBalance(aliceWeightedB);
+
+            await program.methods
+                .swap(new BN(amountIn), new BN(1), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: weightedPool.pool,
+                    oracle: oracleFor(weightedPool.pool),
+                    config: configPda,
+                    poolAuthority: weightedPool.poolAuthority,
+                    sourceMint: weightedPool.mintA,
+                    destinationMint: weightedPool.mintB,
+                    userSourceTokenAccount: aliceWeightedA,
+                    userDestinationTokenAccount: aliceWeightedB,
+                    tokenAVault: weightedPool.vaultA,
+                    tokenBVault: weightedPool.vaultB,
+                    tokenProgram: TOKEN_PROGRAM_ID
+                } as any)
+                .signers([alice])
+                .rpc();
+
+            const amountOut = await getTokenBalance(aliceWeightedB) - aliceB_before;
+            // out = reserve * (1 - (reserve / (reserve + in_after_fee))^(0.8 / 0.2))
+            const reserve = Number(weightedLiquidity.toString());
+            const afterFee = amountIn * 997 / 1000;
+            const expectedOut = reserve * (1 - Math.pow(reserve / (reserve + afterFee), tokenAWeightBps / (10_000 - tokenAWeightBps)));
+
+            assert.ok(amountOut <= expectedOut, `Weighted output ${amountOut} should not exceed ${expectedOut}`);
+            assert.ok(amountOut >= expectedOut * 0.9999, `Weighted output ${amountOut} too far below ${expectedOut}`);
+        });
+
+        it("Fails to initialize a Weighted pool with an out-of-range weight", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            try {
+                await createSeededPool(x.mint, y.mint, weightedLiquidity, weightedCurve, new BN(0), 9_950);
+                assert.fail("Should have failed due to invalid weight");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidCurveParameters", "Expected InvalidCurveParameters error");
+            }
+        });
+    });
+
+    describe("dynamic_fee", () => {
+        const dynamicLiquidity = new BN(1000 * (10 ** decimals));
+        const minFeeBps = 5;
+        const maxFeeBps = 100;
+        let dynamicPool: Awaited<ReturnType<typeof createSeededPool>>;
+        let aliceDynamicA: PublicKey;
+        let aliceDynamicB: PublicKey;
+
+        before(async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            dynamicPool = await createSeededPool(x.mint, y.mint, dynamicLiquidity, constantProductCurve, new BN(0), 0,
+                { dynamic: { minFeeBps, maxFeeBps } },
+            );
+            [aliceDynamicA, aliceDynamicB] = dynamicPool.mintA.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+        });
+
+        const swapAToB = async (amountIn: number, target = dynamicPool) => {
+            await program.methods
+                .swap(new BN(amountIn), new BN(1), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: target.pool,
+                    oracle: oracleFor(target.pool),
+                    config: configPda,
+                    poolAuthority: target.poolAuthority,
+                    sourceMint: target.mintA,
+                    destinationMint: target.mintB,
+                    userSourceTokenAccount: aliceDynamicA,
+                    userDestinationTokenAccount: aliceDynamicB,
+                    tokenAVault: target.vaultA,
+                    tokenBVault: target.vaultB,
+                    tokenProgram: TOKEN_PROGRAM_ID
+                } as any)
+                .signers([alice])
+                .rpc();
+        };
+
+        // Constant product quote for A -> B at the pool's current trading reserves
+        const quoteAToB = async (amountIn: number, feeBps: number, target = dynamicPool): Promise<number> => {
+            const poolAccount = await program.account.liquidityPool.fetch(target.pool);
+            const reserveIn = BigInt(await getTokenBalance(target.vaultA)) - BigInt(poolAccount.protocolFeesA.toString());
+            const reserveOut = BigInt(await getTokenBalance(target.vaultB)) - BigInt(poolAccount.protocolFeesB.toString());
+            const afterFee = BigInt(amountIn) * BigInt(10_000 - feeBps) / BigInt(10_000);
+            return Number(reserveOut * afterFee / (reserveIn + afterFee));
+        };
+
+        it("Stores the fee mode and starts without volatility", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(dynamicPool.pool);
+            assert.ok("dynamic" in poolAccount.feeMode, "Fee mode mismatch");
+            assert.equal(poolAccount.feeMode.dynamic.minFeeBps, minFeeBps, "Min fee mismatch");
+            assert.equal(poolAccount.feeMode.dynamic.maxFeeBps, maxFeeBps, "Max fee mismatch");
+            assert.equal(poolAccount.volatilityBps, 0, "Volatility should start at zero");
+        });
+
+        it("Charges the minimum fee in a quiet pool and raises it after a large move", async () => {
+            const amountIn = 50 * (10 ** decimals);
+
+            const expectedQuiet = await quoteAToB(amountIn, minFeeBps);
+            const aliceB_before = await getTokenBalance(aliceDynamicB);
+            await swapAToB(amountIn);
+            const quietOut = await getTokenBalance(aliceDynamicB) - aliceB_before;
+            assert.equal(quietOut, expectedQuiet, "First swap should pay the minimum fee");
+
+            const poolAccount = await program.account.liquidityPool.fetch(dynamicPool.pool);
+            assert.ok(poolAccount.volatilityBps > 0, "Swap should raise the volatility measure");
+
+            // The same swap now pays more than the minimum fee
+            const atMinimumFee = await quoteAToB(amountIn, minFeeBps);
+            const atMaximumFee = await quoteAToB(amountIn, maxFeeBps);
+            const aliceB_middle = await getTokenBalance(aliceDynamicB);
+            await swapAToB(amountIn);
+            const volatileOut = await getTokenBalance(aliceDynamicB) - aliceB_middle;
+            assert.ok(volatileOut < atMinimumFee, `Output ${volatileOut} should be below the minimum-fee quote ${atMinimumFee}`);
+            assert.ok(volatileOut >= atMaximumFee, `Output ${volatileOut} should not go below the maximum-fee quote ${atMaximumFee}`);
+        });
+
+        it("Raises the dynamic fee after the internal swaps of zap_in and zap_out", async () => {
+            // Same mints as the main dynamic pool, in another fee tier, so it starts quiet
+            const zapPool = await createSeededPool(dynamicPool.mintA, dynamicPool.mintB, dynamicLiquidity, constantProductCurve, new BN(0), 0,
+                { dynamic: { minFeeBps, maxFeeBps } }, poolMaxPriceImpactBps, minFeeBps,
+            );
+            const amountIn = 50 * (10 ** decimals);
+
+            const lp_before = await getTokenBalance(zapPool.aliceLp);
+            await program.methods
+                .zapIn(zapPool.mintA, new BN(amountIn), new BN(1))
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: zapPool.pool,
+                    oracle: oracleFor(zapPool.pool),
+                    config: configPda,
+                    poolAuthority: zapPool.poolAuthority,
+                    tokenAMint: zapPool.mintA,
+                    tokenBMint: zapPool.mintB,
+                    userSourceTokenAccount: aliceDynamicA,
+                    tokenAVault: zapPool.vaultA,
+                    tokenBVault: zapPool.vaultB,
+                    lpMint: zapPool.lpMint,
+                    userLpTokenAccount: zapPool.aliceLp,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .signers([alice])
+                .rpc();
+            const afterZapIn = await program.account.liquidityPool.fetch(zapPool.pool);
+            assert.ok(afterZapIn.volatilityBps > 0, "zap_in should raise the volatility measure");
+
+            await program.methods
+                .zapOut(new BN(await getTokenBalance(zapPool.aliceLp) - lp_before), zapPool.mintA, new BN(1))
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: zapPool.pool,
+                    oracle: oracleFor(zapPool.pool),
+                    poolAuthority: zapPool.poolAuthority,
+                    tokenAMint: zapPool.mintA,
+                    tokenBMint: zapPool.mintB,
+                    lpMint: zapPool.lpMint,
+                    userLpTokenAccount: zapPool.aliceLp,
+                    userDestinationTokenAccount: aliceDynamicA,
+                    tokenAVault: zapPool.vaultA,
+                    tokenBVault: zapPool.vaultB,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .signers([alice])
+                .rpc();
+            const afterZapOut = await program.account.liquidityPool.fetch(zapPool.pool);
+            assert.ok(afterZapOut.volatilityBps > afterZapIn.volatilityBps, "zap_out should raise the volatility measure");
+
+            // A swap after the zaps pays more than the minimum fee
+            const swapIn = 10 * (10 ** decimals);
+            const atMinimumFee = await quoteAToB(swapIn, minFeeBps, zapPool);
+            const aliceB_before = await getTokenBalance(aliceDynamicB);
+            await swapAToB(swapIn, zapPool);
+            const swapOut = await getTokenBalance(aliceDynamicB) - aliceB_before;
+            assert.ok(swapOut < atMinimumFee, `Output ${swapOut} should be below the minimum-fee quote ${atMinimumFee}`);
+        });
+
+        it("Fails to initialize a dynamic fee with min above max", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            try {
+                await createSeededPool(x.mint, y.mint, dynamicLiquidity, constantProductCurve, new BN(0), 0,
+                    { dynamic: { minFeeBps: maxFeeBps, maxFeeBps: minFeeBps } },
+                );
+                assert.fail("Should have failed due to an invalid fee range");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidFee", "Expected InvalidFee error");
+            }
+        });
+
+        it("Fails to initialize a dynamic fee that doesn't bracket the pool's fee tier", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            try {
+                // Seeded as the 5 bps tier while charging 30 to 100 bps
+                await createSeededPool(x.mint, y.mint, dynamicLiquidity, constantProductCurve, new BN(0), 0,
+                    { dynamic: { minFeeBps: 30, maxFeeBps: maxFeeBps } }, poolMaxPriceImpactBps, minFeeBps,
+                );
+                assert.fail("Should have failed due to a fee range outside the tier");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidFeeTier", "Expected InvalidFeeTier error");
+            }
+        });
+
+        it("Fails to initialize a dynamic fee whose bounds aren't configured tiers", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            try {
+                await createSeededPool(x.mint, y.mint, dynamicLiquidity, constantProductCurve, new BN(0), 0,
+                    { dynamic: { minFeeBps: 20, maxFeeBps: 9_000 } },
+                );
+                assert.fail("Should have failed due to fee bounds outside the tiers");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidFeeTier", "Expected InvalidFeeTier error");
+            }
+        });
+    });
+
+    describe("price_impact_limit", () => {
+        const limitLiquidity = new BN(1000 * (10 ** decimals));
+        // Takes about 16% of the output reserve, above the default 10% limit
+        const largeSwap = new BN(200 * (10 ** decimals));
+        let limitPool: Awaited<ReturnType<typeof createSeededPool>>;
+        let aliceLimitA: PublicKey;
+        let aliceLimitB: PublicKey;
+
+        before(async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            limitPool = await createSeededPool(x.mint, y.mint, limitLiquidity);
+            [aliceLimitA, aliceLimitB] = limitPool.mintA.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+        });
+
+        const swapLarge = () => program.methods
+            .swap(largeSwap, new BN(1), null)
+            .accounts({
+                userAuthority: alice.publicKey,
+                pool: limitPool.pool,
+                oracle: oracleFor(limitPool.pool),
+                config: configPda,
+                poolAuthority: limitPool.poolAuthority,
+                sourceMint: limitPool.mintA,
+                destinationMint: limitPool.mintB,
+                userSourceTokenAccount: aliceLimitA,
+                userDestinationTokenAccount: aliceLimitB,
+                tokenAVault: limitPool.vaultA,
+                tokenBVault: limitPool.vaultB,
+                tokenProgram: TOKEN_PROGRAM_ID
+            } as any)
+            .signers([alice])
+            .rpc();
+
+        const setLimit = (admin: Keypair, maxPriceImpactBps: number) => program.methods
+            .setMaxPriceImpact(maxPriceImpactBps)
+            .accounts({
+                config: configPda,
+                pool: limitPool.pool,
+                admin: admin.publicKey,
+            } as any)
+            .signers([admin])
+            .rpc();
+
+        it("Stores the limit set at initialization", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(limitPool.pool);
+            assert.equal(poolAccount.maxPriceImpactBps, poolMaxPriceImpactBps, "Price impact limit mismatch");
+        });
+
+        it("Rejects a swap above the pool's limit", async () => {
+            try {
+                await swapLarge();
+                assert.fail("Should have failed due to price impact");
+            } catch (e) {
+                assert.include(e.toString(), "ExcessivePriceImpact", "Expected ExcessivePriceImpact error");
+            }
+        });
+
+        it("Fails when someone other than the config admin updates the limit", async () => {
+            try {
+                await setLimit(alice, 0);
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Fails to set a limit above 100%", async () => {
+            try {
+                await setLimit(intializer, 10_001);
+                assert.fail("Should have failed due to an invalid limit");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidPriceImpactLimit", "Expected InvalidPriceImpactLimit error");
+            }
+        });
+
+        it("Allows the large swap once the admin disables the limit", async () => {
+            await setLimit(intializer, 0);
+            const poolAccount = await program.account.liquidityPool.fetch(limitPool.pool);
+            assert.equal(poolAccount.maxPriceImpactBps, 0, "Price impact limit should be disabled");
+
+            const aliceB_before = await getTokenBalance(aliceLimitB);
+            await swapLarge();
+            assert.ok(await getTokenBalance(aliceLimitB) > aliceB_before, "Large swap should pay out");
+        });
+    });
+
+    describe("remove_liquidity", () => {
+        const removeAccounts = async () => {
+            const poolAccounts = await getPoolAccounts();
+            return {
+                userAuthority: alice.publicKey,
+                pool: poolPda,
+                oracle: oracleFor(poolPda),
+                poolAuthority: poolAuthorityPda,
+                tokenAMint: poolAccounts.tokenAMint,
+                tokenBMint: poolAccounts.tokenBMint,
+                lpMint: lpMintPda,
+                userTokenAAccount: poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount,
+                userTokenBAccount: poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount,
+                userLpTokenAccount: aliceLpTokenAccount,
+                tokenAVault: poolAccounts.tokenAVault,
+                tokenBVault: poolAccounts.tokenBVault,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            };
+        };
+
+        it("Burns LP shares for a pro-rata share of both vaults", async () => {
+            const aliceLp_before = await getTokenBalance(aliceLpTokenAccount);
+            if (aliceLp_before === 0) {
+                console.log("Skipping test - Alice has no LP shares");
+                return;
+            }
+
+            const lpSupply = Number((await getMint(provider.connection, lpMintPda)).supply);
+            const vaultA_before = await getTokenBalance(poolTokenAVault);
+            const vaultB_before = await getTokenBalance(poolTokenBVault);
+
+            const lpAmount = Math.floor(aliceLp_before / 4);
+            const expectedA = Math.floor(vaultA_before * lpAmount / lpSupply);
+            const expectedB = Math.floor(vaultB_before * lpAmount / lpSupply);
+
+            await program.methods
+                .removeLiquidity(new BN(lpAmount), new BN(expectedA), new BN(expectedB))
+                .accounts(await removeAccounts() as any)
+                .signers([alice])
+                .rpc();
+
+            const aliceLp_after = await getTokenBalance(aliceLpTokenAccount);
+            const vaultA_after = await getTokenBalance(poolTokenAVault);
+            const vaultB_after = await getTokenBalance(poolTokenBVault);
+
+            assert.equal(aliceLp_before - aliceLp_after, lpAmount, "LP shares were not burned");
+            assert.equal(vaultA_before - vaultA_after, expectedA, "Vault A payout mismatch");
+            assert.equal(vaultB_before - vaultB_after, expectedB, "Vault B payout mismatch");
+        });
+
+        it("Fails when the payout is below the requested minimums", async () => {
+            const aliceLp = await getTokenBalance(aliceLpTokenAccount);
+            const vaultA = await getTokenBalance(poolTokenAVault);
+
+            try {
+                await program.methods
+                    .removeLiquidity(new BN(Math.floor(aliceLp / 10)), new BN(vaultA), new BN(0))
+                    .accounts(await removeAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to slippage");
+            } catch (e) {
+                assert.include(e.toString(), "SlippageExceeded", "Expected SlippageExceeded error");
+            }
+        });
+
+        it("Fails to remove zero LP shares", async () => {
+            try {
+                await program.methods
+                    .removeLiquidity(new BN(0), new BN(0), new BN(0))
+                    .accounts(await removeAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to zero amount");
+            } catch (e) {
+                assert.include(e.toString(), "ZeroAmount", "Expected ZeroAmount error");
+            }
+        });
+    });
+
+    describe("zap_out", () => {
+        const zapOutAccounts = async (userDestinationTokenAccount: PublicKey) => {
+            const poolAccounts = await getPoolAccounts();
+            return {
+                userAuthority: alice.publicKey,
+                pool: poolPda,
+                oracle: oracleFor(poolPda),
+                poolAuthority: poolAuthorityPda,
+                tokenAMint: poolAccounts.tokenAMint,
+                tokenBMint: poolAccounts.tokenBMint,
+                lpMint: lpMintPda,
+                userLpTokenAccount: aliceLpTokenAccount,
+                userDestinationTokenAccount,
+                tokenAVault: poolAccounts.tokenAVault,
+                tokenBVault: poolAccounts.tokenBVault,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            };
+        };
+
+        it("Burns LP shares and pays out a single token", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const aliceB = poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount;
+            const aliceA = poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount;
+
+            const aliceLp_before = await getTokenBalance(aliceLpTokenAccount);
+            const aliceA_before = await getTokenBalance(aliceA);
+            const aliceB_before = await getTokenBalance(aliceB);
+            const vaultA_before = await getTokenBalance(poolAccounts.tokenAVault);
+            const lpAmount = Math.floor(aliceLp_before / 10);
+
+            await program.methods
+                .zapOut(new BN(lpAmount), poolAccounts.tokenBMint, new BN(1))
+                .accounts(await zapOutAccounts(aliceB) as any)
+                .signers([alice])
+                .rpc();
+
+            assert.equal(aliceLp_before - await getTokenBalance(aliceLpTokenAccount), lpAmount, "LP shares were not burned");
+            assert.ok(await getTokenBalance(aliceB) > aliceB_before, "Alice did not receive token B");
+            assert.equal(await getTokenBalance(aliceA), aliceA_before, "Alice A balance should not change");
+            assert.equal(await getTokenBalance(poolAccounts.tokenAVault), vaultA_before, "Vault A balance should not change");
+        });
+
+        it("Fails when the payout is below min_amount_out", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const aliceB = poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount;
+            const vaultB = await getTokenBalance(poolAccounts.tokenBVault);
+
+            try {
+                await program.methods
+                    .zapOut(new BN(1000), poolAccounts.tokenBMint, new BN(vaultB))
+                    .accounts(await zapOutAccounts(aliceB) as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to slippage");
+            } catch (e) {
+                assert.include(e.toString(), "SlippageExceeded", "Expected SlippageExceeded error");
+            }
+        });
+    });
+
+    describe("minimum_liquidity", () => {
+        // A tiny seed leaves the pool nearly empty, the setup a donation attack needs
+        const attackSeed = new BN(2_000);
+        const donation = 1_000 * (10 ** decimals);
+        let attackPool: Awaited<ReturnType<typeof createSeededPool>>;
+        let aliceAttackA: PublicKey;
+        let aliceAttackB: PublicKey;
+
+        before(async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            attackPool = await createSeededPool(x.mint, y.mint, attackSeed);
+            [aliceAttackA, aliceAttackB] = attackPool.mintA.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+
+            // The attacker (here the pool's initializer) donates straight to the vaults to inflate the share price
+            for (const [mint, vault] of [[attackPool.mintA, attackPool.vaultA], [attackPool.mintB, attackPool.vaultB]]) {
+                const source = await fundIntializer(mint, donation);
+                await transfer(provider.connection, payer, source, vault, intializer, BigInt(donation));
+            }
+        });
+
+        const deposit = (amount: number) => program.methods
+            .addLiquidity(new BN(amount), new BN(amount), null)
+            .accounts({
+                userAuthority: alice.publicKey,
+                pool: attackPool.pool,
+                oracle: oracleFor(attackPool.pool),
+                config: configPda,
+                poolAuthority: attackPool.poolAuthority,
+                tokenAMint: attackPool.mintA,
+                tokenBMint: attackPool.mintB,
+                userTokenAAccount: aliceAttackA,
+                userTokenBAccount: aliceAttackB,
+                tokenAVault: attackPool.vaultA,
+                tokenBVault: attackPool.vaultB,
+                lpMint: attackPool.lpMint,
+                userLpTokenAccount: attackPool.aliceLp,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            } as any)
+            .signers([alice])
+            .rpc();
+
+        const withdraw = (lpAmount: number) => program.methods
+            .removeLiquidity(new BN(lpAmount), new BN(0), new BN(0))
+            .accounts({
+                userAuthority: alice.publicKey,
+                pool: attackPool.pool,
+                oracle: oracleFor(attackPool.pool),
+                poolAuthority: attackPool.poolAuthority,
+                tokenAMint: attackPool.mintA,
+                tokenBMint: attackPool.mintB,
+                lpMint: attackPool.lpMint,
+                userTokenAAccount: aliceAttackA,
+                userTokenBAccount: aliceAttackB,
+                userLpTokenAccount: attackPool.aliceLp,
+                tokenAVault: attackPool.vaultA,
+                tokenBVault: attackPool.vaultB,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            } as any)
+            .signers([alice])
+            .rpc();
+
+        it("Fails to create a pool whose first deposit doesn't exceed the locked minimum", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            try {
+                await createSeededPool(x.mint, y.mint, new BN(minimumLiquidity));
+                assert.fail("Should have failed due to insufficient initial liquidity");
+            } catch (e) {
+                assert.include(e.toString(), "InsufficientInitialLiquidity", "Expected InsufficientInitialLiquidity error");
+            }
+        });
+
+        it("Locks the minimum liquidity on the first deposit", async () => {
+            const lpSupply = Number((await getMint(provider.connection, attackPool.lpMint)).supply);
+            assert.equal(lpSupply, attackSeed.toNumber(), "LP supply mismatch");
+            assert.equal(await getTokenBalance(lockedLpFor(attackPool.lpMint, attackPool.poolAuthority)), minimumLiquidity, "Locked LP balance mismatch");
+            assert.equal(await getTokenBalance(intializerAtaFor(attackPool.lpMint)), attackSeed.toNumber() - minimumLiquidity, "Initializer LP balance mismatch");
+        });
+
+        it("Rejects a deposit that would mint zero shares after a donation", async () => {
+            const aliceA_before = await getTokenBalance(aliceAttackA);
+            try {
+                // Worth far less than one share at the inflated price
+                await deposit(100_000);
+                assert.fail("Should have failed due to a zero-share deposit");
+            } catch (e) {
+                assert.include(e.toString(), "ZeroLpShares", "Expected ZeroLpShares error");
+            }
+            assert.equal(await getTokenBalance(aliceAttackA), aliceA_before, "Rejected deposit should not move funds");
+        });
+
+        it("Limits a depositor's loss after a donation to rounding", async () => {
+            const victimDeposit = 1_000 * (10 ** decimals);
+            const aliceA_before = await getTokenBalance(aliceAttackA);
+            const aliceB_before = await getTokenBalance(aliceAttackB);
+
+            await deposit(victimDeposit);
+            const aliceLp = await getTokenBalance(attackPool.aliceLp);
+            assert.ok(aliceLp > 0, "Deposit should mint LP shares");
+            await withdraw(aliceLp);
+
+            // Alice gets back all but less than 0.1% of her deposit
+            const lossA = aliceA_before - await getTokenBalance(aliceAttackA);
+            const lossB = aliceB_before - await getTokenBalance(aliceAttackB);
+            assert.ok(lossA < victimDeposit / 1000, `Victim lost ${lossA} of token A`);
+            assert.ok(lossB < victimDeposit / 1000, `Victim lost ${lossB} of token B`);
+
+            // The attacker's shares are worth far less than the donation, most of which sits behind the locked shares
+            const lpSupply = Number((await getMint(provider.connection, attackPool.lpMint)).supply);
+            const attackerShares = await getTokenBalance(intializerAtaFor(attackPool.lpMint));
+            const attackerValueA = Math.floor(attackerShares * await getTokenBalance(attackPool.vaultA) / lpSupply);
+            assert.ok(attackerValueA < donation, "Attacker should not recover the donation");
+        });
+    });
+
+    describe("twap_oracle", () => {
+        const twapAccounts = async () => ({
+            pool: poolPda,
+            oracle: oracleFor(poolPda),
+        });
+
+        it("Records observations for the pool", async () => {
+            const oracle = await program.account.priceOracle.fetch(oracleFor(poolPda));
+            assert.ok(oracle.pool.equals(poolPda), "Oracle pool mismatch");
+            assert.ok(oracle.observationCount > 0, "Oracle has no observations");
+
+            const pool = await program.account.liquidityPool.fetch(poolPda);
+            assert.ok(pool.priceACumulativeX64.gt(new BN(0)), "Price A accumulator did not grow");
+            assert.ok(pool.priceBCumulativeX64.gt(new BN(0)), "Price B accumulator did not grow");
+        });
+
+        it("Returns a positive average price over a covered window", async () => {
+            const twap = await program.methods
+                .getTwap(1)
+                .accounts(await twapAccounts() as any)
+                .view();
+            assert.equal(twap.windowSeconds, 1, "Window mismatch");
+            assert.ok(twap.priceAX64.gt(new BN(0)), "TWAP of Token A should be positive");
+            assert.ok(twap.priceBX64.gt(new BN(0)), "TWAP of Token B should be positive");
+        });
+
+        it("Fails for a window older than the recorded history", async () => {
+            try {
+                await program.methods
+                    .getTwap(1_000_000_000)
+                    .accounts(await twapAccounts() as any)
+                    .view();
+                assert.fail("Should have failed due to missing history");
+            } catch (e) {
+                assert.include(e.toString(), "InsufficientOracleHistory", "Expected InsufficientOracleHistory error");
+            }
+        });
+
+        it("Fails for an empty window", async () => {
+            try {
+                await program.methods
+                    .getTwap(0)
+                    .accounts(await twapAccounts() as any)
+                    .view();
+                assert.fail("Should have failed due to an empty window");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidTwapWindow", "Expected InvalidTwapWindow error");
+            }
+        });
+
+        it("Keeps its history through a burst of swaps", async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const burstPool = await createSeededPool(x.mint, y.mint, new BN(1000 * (10 ** decimals)));
+            const [aliceA, aliceB] = burstPool.mintA.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+            const before = await program.account.priceOracle.fetch(oracleFor(burstPool.pool));
+
+            for (let i = 0; i < 8; i++) {
+                await program.methods
+                    .swap(new BN(1000), new BN(1), null)
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        pool: burstPool.pool,
+                        oracle: oracleFor(burstPool.pool),
+                        config: configPda,
+                        poolAuthority: burstPool.poolAuthority,
+                        sourceMint: burstPool.mintA,
+                        destinationMint: burstPool.mintB,
+                        userSourceTokenAccount: aliceA,
+                        userDestinationTokenAccount: aliceB,
+                        tokenAVault: burstPool.vaultA,
+                        tokenBVault: burstPool.vaultB,
+                        tokenProgram: TOKEN_PROGRAM_ID
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+            }
+
+            // Observations are at least a minute apart, so the burst can't push the pool's
+            // creation out of the buffer
+            const after = await program.account.priceOracle.fetch(oracleFor(burstPool.pool));
+            assert.ok(after.observationCount - before.observationCount <= 1, "A burst should add at most one observation");
+            assert.equal(after.observations[0].timestamp.toString(), before.observations[0].timestamp.toString(), "The oldest observation was overwritten");
+        });
+
+        it("Ignores tokens sent straight to a vault until the next swap or liquidity change", async () => {
+            const poolBefore = await program.account.liquidityPool.fetch(poolPda);
+            const aliceTokenA = poolBefore.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount;
+            const vaultA_before = await getTokenBalance(poolBefore.tokenAVault);
+            assert.equal(
+                poolBefore.reserveA.toNumber(),
+                vaultA_before - poolBefore.protocolFeesA.toNumber(),
+                "Synced reserve should match the vault after the last swap"
+            );
+
+            await transfer(provider.connection, alice, aliceTokenA, poolBefore.tokenAVault, alice, 50 * (10 ** decimals));
+
+            const poolAfter = await program.account.liquidityPool.fetch(poolPda);
+            assert.ok(poolAfter.reserveA.eq(poolBefore.reserveA), "A donation should not move the synced reserve");
+            assert.ok(poolAfter.reserveB.eq(poolBefore.reserveB), "A donation should not move the synced reserve");
+        });
+    });
+
+    describe("pause", () => {
+        const pauseLiquidity = new BN(1000 * (10 ** decimals));
+        let pausePool: Awaited<ReturnType<typeof createSeededPool>>;
+        let alicePauseA: PublicKey;
+        let alicePauseB: PublicKey;
+
+        before(async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            pausePool = await createSeededPool(x.mint, y.mint, pauseLiquidity);
+            [alicePauseA, alicePauseB] = pausePool.mintA.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+            // Alice needs LP shares to test withdrawals while paused
+            await addSmall();
+        });
+
+        const swapSmall = () => program.methods
+            .swap(new BN(1 * (10 ** decimals)), new BN(1), null)
+            .accounts({
+                userAuthority: alice.publicKey,
+                pool: pausePool.pool,
+                oracle: oracleFor(pausePool.pool),
+                config: configPda,
+                poolAuthority: pausePool.poolAuthority,
+                sourceMint: pausePool.mintA,
+                destinationMint: pausePool.mintB,
+                userSourceTokenAccount: alicePauseA,
+                userDestinationTokenAccount: alicePauseB,
+                tokenAVault: pausePool.vaultA,
+                tokenBVault: pausePool.vaultB,
+                tokenProgram: TOKEN_PROGRAM_ID
+            } as any)
+            .signers([alice])
+            .rpc();
+
+        const addSmall = () => program.methods
+            .addLiquidity(new BN(1 * (10 ** decimals)), new BN(1 * (10 ** decimals)), null)
+            .accounts({
+                userAuthority: alice.publicKey,
+                pool: pausePool.pool,
+                oracle: oracleFor(pausePool.pool),
+                config: configPda,
+                poolAuthority: pausePool.poolAuthority,
+                tokenAMint: pausePool.mintA,
+                tokenBMint: pausePool.mintB,
+                userTokenAAccount: alicePauseA,
+                userTokenBAccount: alicePauseB,
+                tokenAVault: pausePool.vaultA,
+                tokenBVault: pausePool.vaultB,
+                lpMint: pausePool.lpMint,
+                userLpTokenAccount: pausePool.aliceLp,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            } as any)
+            .signers([alice])
+            .rpc();
+
+        const removeSmall = () => program.methods
+            .removeLiquidity(new BN(1 * (10 ** decimals)), new BN(0), new BN(0))
+            .accounts({
+                userAuthority: alice.publicKey,
+                pool: pausePool.pool,
+                oracle: oracleFor(pausePool.pool),
+                poolAuthority: pausePool.poolAuthority,
+                tokenAMint: pausePool.mintA,
+                tokenBMint: pausePool.mintB,
+                lpMint: pausePool.lpMint,
+                userTokenAAccount: alicePauseA,
+                userTokenBAccount: alicePauseB,
+                userLpTokenAccount: pausePool.aliceLp,
+                tokenAVault: pausePool.vaultA,
+                tokenBVault: pausePool.vaultB,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            } as any)
+            .signers([alice])
+            .rpc();
+
+        const zapOutSmall = () => program.methods
+            .zapOut(new BN(1 * (10 ** decimals)), pausePool.mintB, new BN(1))
+            .accounts({
+                userAuthority: alice.publicKey,
+                pool: pausePool.pool,
+                oracle: oracleFor(pausePool.pool),
+                poolAuthority: pausePool.poolAuthority,
+                tokenAMint: pausePool.mintA,
+                tokenBMint: pausePool.mintB,
+                lpMint: pausePool.lpMint,
+                userLpTokenAccount: pausePool.aliceLp,
+                userDestinationTokenAccount: alicePauseB,
+                tokenAVault: pausePool.vaultA,
+                tokenBVault: pausePool.vaultB,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            } as any)
+            .signers([alice])
+            .rpc();
+
+        const setPaused = (admin: Keypair, paused: boolean) => program.methods
+            .setPaused(paused)
+            .accounts({
+                config: configPda,
+                admin: admin.publicKey,
+            } as any)
+            .signers([admin])
+            .rpc();
+
+        const setPoolPaused = (admin: Keypair, paused: boolean) => program.methods
+            .setPoolPaused(paused)
+            .accounts({
+                config: configPda,
+                pool: pausePool.pool,
+                admin: admin.publicKey,
+            } as any)
+            .signers([admin])
+            .rpc();
+
+        const expectPaused = async (action: () => Promise<string>) => {
+            try {
+                await action();
+                assert.fail("Should have failed while paused");
+            } catch (e) {
+                assert.include(e.toString(), "Paused", "Expected Paused error");
+            }
+        };
+
+        it("Fails when a non-admin pauses the program", async () => {
+            try {
+                await setPaused(alice, true);
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Blocks swaps and deposits but not withdrawals while globally paused", async () => {
+            await setPaused(intializer, true);
+            try {
+                const config = await program.account.programConfig.fetch(configPda);
+                assert.isTrue(config.paused, "Config should be paused");
+
+                await expectPaused(swapSmall);
+                await expectPaused(addSmall);
+
+                const lp_before = await getTokenBalance(pausePool.aliceLp);
+                await removeSmall();
+                assert.equal(lp_before - await getTokenBalance(pausePool.aliceLp), 1 * (10 ** decimals), "Withdrawal should burn LP shares");
+
+                const aliceB_before = await getTokenBalance(alicePauseB);
+                await zapOutSmall();
+                assert.ok(await getTokenBalance(alicePauseB) > aliceB_before, "Zap out should pay out");
+            } finally {
+                await setPaused(intializer, false);
+            }
+        });
+
+        it("Blocks swaps and deposits on a single paused pool", async () => {
+            await setPoolPaused(intializer, true);
+            try {
+                const poolAccount = await program.account.liquidityPool.fetch(pausePool.pool);
+                assert.isTrue(poolAccount.paused, "Pool should be paused");
+
+                await expectPaused(swapSmall);
+                await expectPaused(addSmall);
+                await removeSmall();
+                await zapOutSmall();
+            } finally {
+                await setPoolPaused(intializer, false);
+            }
+        });
+
+        it("Resumes swaps and deposits once unpaused", async () => {
+            const aliceB_before = await getTokenBalance(alicePauseB);
+            await swapSmall();
+            assert.ok(await getTokenBalance(alicePauseB) > aliceB_before, "Swap should pay out");
+
+            const lp_before = await getTokenBalance(pausePool.aliceLp);
+            await addSmall();
+            assert.ok(await getTokenBalance(pausePool.aliceLp) > lp_before, "Deposit should mint LP shares");
+        });
+    });
+
+    describe("admin_transfer", () => {
+        const proposeAdmin = (admin: Keypair, newAdmin: PublicKey) => program.methods
+            .proposeAdmin(newAdmin)
+            .accounts({
+                config: configPda,
+                admin: admin.publicKey,
+            } as any)
+            .signers([admin])
+            .rpc();
+
+        const acceptAdmin = (newAdmin: Keypair) => program.methods
+            .acceptAdmin()
+            .accounts({
+                config: configPda,
+                newAdmin: newAdmin.publicKey,
+            } as any)
+            .signers([newAdmin])
+            .rpc();
+
+        it("Fails when a non-admin proposes a new admin", async () => {
+            try {
+                await proposeAdmin(alice, alice.publicKey);
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Keeps the current admin until the proposed admin accepts", async () => {
+            await proposeAdmin(intializer, bob.publicKey);
+
+            const config = await program.account.programConfig.fetch(configPda);
+            assert.ok(config.admin.equals(intializer.publicKey), "Admin should not change on proposal");
+            assert.ok(config.pendingAdmin.equals(bob.publicKey), "Pending admin mismatch");
+        });
+
+        it("Fails when someone other than the proposed admin accepts", async () => {
+            try {
+                await acceptAdmin(alice);
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Transfers the admin role once the proposed admin accepts", async () => {
+            await acceptAdmin(bob);
+
+            let config = await program.account.programConfig.fetch(configPda);
+            assert.ok(config.admin.equals(bob.publicKey), "Admin should be transferred");
+            assert.isNull(config.pendingAdmin, "Pending admin should be cleared");
+
+            // Hand the role back so later tests can keep using the original admin
+            await proposeAdmin(bob, intializer.publicKey);
+            await acceptAdmin(intializer);
+            config = await program.account.programConfig.fetch(configPda);
+            assert.ok(config.admin.equals(intializer.publicKey), "Admin should be transferred back");
+        });
+    });
+
+    describe("collect_protocol_fees", () => {
+        let treasuryTokenAAccount: PublicKey;
+        let treasuryTokenBAccount: PublicKey;
+
+        before(async () => {
+            const poolAccounts = await getPoolAccounts();
+            if (!poolAccounts) {
+                return;
+            }
+            treasuryTokenAAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, poolAccounts.tokenAMint, intializer.publicKey)).address;
+            treasuryTokenBAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, poolAccounts.tokenBMint, intializer.publicKey)).address;
+        });
+
+        const collectAccounts = async (admin: PublicKey) => {
+            const poolAccounts = await getPoolAccounts();
+            return {
+                admin,
+                pool: poolPda,
+                config: configPda,
+                poolAuthority: poolAuthorityPda,
+                tokenAMint: poolAccounts.tokenAMint,
+                tokenBMint: poolAccounts.tokenBMint,
+                tokenAVault: poolAccounts.tokenAVault,
+                tokenBVault: poolAccounts.tokenBVault,
+                treasuryTokenAAccount,
+                treasuryTokenBAccount,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            };
+        };
+
+        it("Accrues protocol fees from swaps", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            assert.ok(
+                poolAccount.protocolFeesA.gt(new BN(0)) || poolAccount.protocolFeesB.gt(new BN(0)),
+                "Expected protocol fees to have accrued from earlier swaps"
+            );
+        });
+
+        it("Fails when the signer is not the config admin", async () => {
+            try {
+                await program.methods
+                    .collectProtocolFees()
+                    .accounts(await collectAccounts(bob.publicKey) as any)
+                    .signers([bob])
+                    .rpc();
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Fails when the fees would go to an account the treasury doesn't own", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const aliceAsTreasuryA = poolAccounts.tokenAMint.equals(tokenAMint) ? aliceTokenAAccount : aliceTokenBAccount;
+            try {
+                await program.methods
+                    .collectProtocolFees()
+                    .accounts({ ...await collectAccounts(intializer.publicKey), treasuryTokenAAccount: aliceAsTreasuryA } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed due to a non-treasury account");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidOwner", "Expected InvalidOwner error");
+            }
+        });
+
+        it("Moves accrued protocol fees to the treasury", async () => {
+            const poolBefore = await program.account.liquidityPool.fetch(poolPda);
+            const treasuryA_before = await getTokenBalance(treasuryTokenAAccount);
+            const treasuryB_before = await getTokenBalance(treasuryTokenBAccount);
+            const vaultA_before = await getTokenBalance(poolBefore.tokenAVault);
+            const vaultB_before = await getTokenBalance(poolBefore.tokenBVault);
+
+            await program.methods
+                .collectProtocolFees()
+                .accounts(await collectAccounts(intializer.publicKey) as any)
+                .signers([intializer])
+                .rpc();
+
+            const poolAfter = await program.account.liquidityPool.fetch(poolPda);
+            assert.equal(await getTokenBalance(treasuryTokenAAccount) - treasuryA_before, poolBefore.protocolFeesA.toNumber(), "Treasury A mismatch");
+            assert.equal(await getTokenBalance(treasuryTokenBAccount) - treasuryB_before, poolBefore.protocolFeesB.toNumber(), "Treasury B mismatch");
+            assert.equal(vaultA_before - await getTokenBalance(poolBefore.tokenAVault), poolBefore.protocolFeesA.toNumber(), "Vault A mismatch");
+            assert.equal(vaultB_before - await getTokenBalance(poolBefore.tokenBVault), poolBefore.protocolFeesB.toNumber(), "Vault B mismatch");
+            assert.ok(poolAfter.protocolFeesA.isZero() && poolAfter.protocolFeesB.isZero(), "Protocol fees were not reset");
+        });
+    });
+
+    describe("concentrated_liquidity", () => {
+        const tickSpacing = 64;
+        const ticksPerArray = 32 * tickSpacing;
+        // Price 1.0: sqrt(1) in Q64.64
+        const initialSqrtPrice = new BN(1).shln(64);
+        const wideLiquidity = new BN(10_000_000_000);
+        const narrowLiquidity = new BN(100_000_000_000);
+        const maxDeposit = new BN(1000 * (10 ** decimals));
+        let clPool: PublicKey;
+        let mintA: PublicKey;
+        let mintB: PublicKey;
+        let vaultA: PublicKey;
+        let vaultB: PublicKey;
+        let aliceA: PublicKey;
+        let aliceB: PublicKey;
+        let treasuryA: PublicKey;
+        let treasuryB: PublicKey;
+
+        const i32Le = (value: number) => {
+            const buffer = Buffer.alloc(4);
+            buffer.writeInt32LE(value);
+            return buffer;
+        };
+        const tickArrayPda = (start: number) => PublicKey.findProgramAddressSync(
+            [Buffer.from("tick_array"), clPool.toBuffer(), i32Le(start)],
+            program.programId
+        )[0];
+        const positionPda = (owner: PublicKey, lower: number, upper: number) => PublicKey.findProgramAddressSync(
+            [Buffer.from("position"), clPool.toBuffer(), owner.toBuffer(), i32Le(lower), i32Le(upper)],
+            program.programId
+        )[0];
+        const tickArrayStart = (tick: number) => Math.floor(tick / ticksPerArray) * ticksPerArray;
+        // Like constant-function pools, concentrated pools are PDAs of the sorted pair and the fee tier
+        const clPoolFor = (feeBps: number) => PublicKey.findProgramAddressSync(
+            [Buffer.from("cl_pool"), mintA.toBuffer(), mintB.toBuffer(), new BN(feeBps).toArrayLike(Buffer, "le", 2)],
+            program.programId
+        )[0];
+        const initializeClPool = async (pool: PublicKey, feeBps: number | null) => program.methods
+            .initializeConcentratedPool(tickSpacing, feeBps, initialSqrtPrice, 0)
+            .accounts({
+                config: configPda,
+                tokenAMint: mintA,
+                tokenBMint: mintB,
+                pool,
+                poolAuthority: authorityFor(pool),
+                tokenAVault: await getAssociatedTokenAddress(mintA, authorityFor(pool), true),
+                tokenBVault: await getAssociatedTokenAddress(mintB, authorityFor(pool), true),
+                oracle: oracleFor(pool),
+                ...(await poolIndexAccountsFor(mintA, mintB)),
+                initializer: intializer.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            } as any)
+            .signers([intializer])
+            .rpc();
+        const modifyAccounts = (owner: PublicKey, lower: number, upper: number) => ({
+            pool: clPool,
+            config: configPda,
+            poolAuthority: authorityFor(clPool),
+            position: positionPda(owner, lower, upper),
+            tickArrayLower: tickArrayPda(tickArrayStart(lower)),
+            tickArrayUpper: tickArrayPda(tickArrayStart(upper)),
+            tokenAMint: mintA,
+            tokenBMint: mintB,
+            userTokenAAccount: aliceA,
+            userTokenBAccount: aliceB,
+            tokenAVault: vaultA,
+            tokenBVault: vaultB,
+            owner,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        });
+        const swapAccounts = () => ({
+            sourceMint: mintA,
+            destinationMint: mintB,
+            pool: clPool,
+            oracle: oracleFor(clPool),
+            config: configPda,
+            poolAuthority: authorityFor(clPool),
+            userSourceTokenAccount: aliceA,
+            userDestinationTokenAccount: aliceB,
+            tokenAVault: vaultA,
+            tokenBVault: vaultB,
+            userAuthority: alice.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        });
+        const collectAccounts = (admin: PublicKey) => ({
+            pool: clPool,
+            tokenAMint: mintA,
+            tokenBMint: mintB,
+            poolAuthority: authorityFor(clPool),
+            tokenAVault: vaultA,
+            tokenBVault: vaultB,
+            config: configPda,
+            treasuryTokenAAccount: treasuryA,
+            treasuryTokenBAccount: treasuryB,
+            admin,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        });
+        const openWithLiquidity = async (lower: number, upper: number, liquidity: BN) => {
+            await program.methods
+                .openPosition(lower, upper)
+                .accounts({
+                    pool: clPool,
+                    config: configPda,
+                    position: positionPda(alice.publicKey, lower, upper),
+                    owner: alice.publicKey,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([alice])
+                .rpc();
+            await program.methods
+                .increaseLiquidity(liquidity, maxDeposit, maxDeposit)
+                .accounts(modifyAccounts(alice.publicKey, lower, upper) as any)
+                .signers([alice])
+                .rpc();
+        };
+
+        before(async () => {
+            const x = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            const y = await setupToken(mintAuthority, alice.publicKey, initialMintAmount);
+            [mintA, mintB] = [x.mint, y.mint].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            [aliceA, aliceB] = mintA.equals(x.mint) ? [x.ata, y.ata] : [y.ata, x.ata];
+            clPool = clPoolFor(poolFeeBps);
+            vaultA = await getAssociatedTokenAddress(mintA, authorityFor(clPool), true);
+            vaultB = await getAssociatedTokenAddress(mintB, authorityFor(clPool), true);
+            treasuryA = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, mintA, intializer.publicKey)).address;
+            treasuryB = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, mintB, intializer.publicKey)).address;
+
+            await initializeClPool(clPool, poolFeeBps);
+
+            for (const start of [-ticksPerArray, 0]) {
+                await program.methods
+                    .initializeTickArray(start)
+                    .accounts({
+                        pool: clPool,
+                        tickArray: tickArrayPda(start),
+                        payer: alice.publicKey,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+            }
+        });
+
+        it("Starts at the initial price with no liquidity", async () => {
+            const poolAccount = await program.account.concentratedPool.fetch(clPool);
+            assert.ok(poolAccount.sqrtPriceX64.eq(initialSqrtPrice), "Sqrt price mismatch");
+            assert.equal(poolAccount.tickCurrent, 0, "Tick mismatch");
+            assert.ok(poolAccount.liquidity.isZero(), "Pool should start without liquidity");
+            assert.equal(poolAccount.feeTierBps, poolFeeBps, "Fee tier mismatch");
+
+            // The vaults belong to the pool's authority PDA, not the pool account
+            const vaultAccount = await getAccount(provider.connection, vaultA);
+            assert.ok(vaultAccount.owner.equals(authorityFor(clPool)), "Vault owner mismatch");
+            assert.ok(poolAccount.feeNumerator.eq(poolFeeNumerator), "Pool fee numerator mismatch");
+            assert.ok(poolAccount.feeDenominator.eq(poolFeeDenominator), "Pool fee denominator mismatch");
+        });
+
+        it("Creates a second concentrated pool for the pair at another fee tier", async () => {
+            const otherTier = feeTiersBps.find((tier) => tier !== poolFeeBps);
+            const otherPool = clPoolFor(otherTier);
+            await initializeClPool(otherPool, otherTier);
+
+            const poolAccount = await program.account.concentratedPool.fetch(otherPool);
+            assert.equal(poolAccount.feeTierBps, otherTier, "Fee tier mismatch");
+
+            // Concentrated pools are listed in their mints' pool indexes too
+            const page = await program.account.mintPoolIndexPage.fetch(poolIndexPageFor(mintA, 0));
+            assert.deepEqual(page.pools.map((p) => p.toBase58()), [clPool.toBase58(), otherPool.toBase58()], "Mint A pools mismatch");
+        });
+
+        it("Fails to create a concentrated pool with a fee that isn't a configured tier", async () => {
+            try {
+                await initializeClPool(clPoolFor(25), 25);
+                assert.fail("Should have failed due to an invalid fee tier");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidFeeTier", "Expected InvalidFeeTier error");
+            }
+        });
+
+        it("Deposits both tokens for a range around the current price", async () => {
+            const aliceA_before = await getTokenBalance(aliceA);
+            const aliceB_before = await getTokenBalance(aliceB);
+
+            await openWithLiquidity(-1024, 1024, wideLiquidity);
+            await openWithLiquidity(-128, 128, narrowLiquidity);
+
+            const poolAccount = await program.account.concentratedPool.fetch(clPool);
+            assert.ok(poolAccount.liquidity.eq(wideLiquidity.add(narrowLiquidity)), "Active liquidity mismatch");
+            assert.ok(aliceA_before - await getTokenBalance(aliceA) > 0, "No Token A deposited");
+            assert.ok(aliceB_before - await getTokenBalance(aliceB) > 0, "No Token B deposited");
+
+            const position = await program.account.position.fetch(positionPda(alice.publicKey, -128, 128));
+            assert.ok(position.liquidity.eq(narrowLiquidity), "Position liquidity mismatch");
+        });
+
+        it("Crosses ticks when a swap moves the price out of a range", async () => {
+            const amountIn = 800 * (10 ** decimals);
+            const aliceB_before = await getTokenBalance(aliceB);
+
+            await program.methods
+                .swapConcentrated(new BN(amountIn), new BN(1), null)
+                .accounts(swapAccounts() as any)
+                .remainingAccounts([0, -ticksPerArray].map((start) => ({
+                    pubkey: tickArrayPda(start),
+                    isWritable: true,
+                    isSigner: false,
+                })))
+                .signers([alice])
+                .rpc();
+
+            const poolAccount = await program.account.concentratedPool.fetch(clPool);
+            assert.ok(poolAccount.tickCurrent < -128, `Price should have left the narrow range, tick ${poolAccount.tickCurrent}`);
+            assert.ok(poolAccount.tickCurrent >= -1024, "Price should still be inside the wide range");
+            assert.ok(poolAccount.liquidity.eq(wideLiquidity), "Only the wide position should be active");
+            const amountOut = await getTokenBalance(aliceB) - aliceB_before;
+            assert.ok(amountOut > 0 && amountOut < amountIn, `Unexpected output ${amountOut}`);
+
+            // The protocol's share of the Token A fee is set aside
+            const feeA = Math.floor(amountIn * poolFeeNumerator.toNumber() / poolFeeDenominator.toNumber());
+            assert.ok(poolAccount.protocolFeesA.gt(new BN(0)), "Protocol fees should have accrued");
+            assert.ok(poolAccount.protocolFeesA.lte(new BN(feeA)), "Protocol fee above the swap fee");
+            assert.ok(poolAccount.protocolFeesB.isZero(), "No Token B fees expected");
+        });
+
+        it("Records the concentrated pool's price in its oracle", async () => {
+            const oracle = await program.account.priceOracle.fetch(oracleFor(clPool));
+            assert.ok(oracle.pool.equals(clPool), "Oracle pool mismatch");
+            assert.ok(oracle.observationCount > 0, "Oracle has no observations");
+
+            const twap = await program.methods
+                .getConcentratedTwap(1)
+                .accounts({ pool: clPool, oracle: oracleFor(clPool) } as any)
+                .view();
+            assert.ok(twap.priceAX64.gt(new BN(0)), "TWAP of Token A should be positive");
+            assert.ok(twap.priceBX64.gt(new BN(0)), "TWAP of Token B should be positive");
+        });
+
+        it("Fails a concentrated swap after the deadline", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            try {
+                await program.methods
+                    .swapConcentrated(new BN(10 ** decimals), new BN(1), new BN(now - 600))
+                    .accounts(swapAccounts() as any)
+                    .remainingAccounts([{ pubkey: tickArrayPda(-ticksPerArray), isWritable: true, isSigner: false }])
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an expired deadline");
+            } catch (e) {
+                assert.include(e.toString(), "Expired", "Expected Expired error");
+            }
+        });
+
+        it("Fails to collect concentrated protocol fees as someone other than the config admin", async () => {
+            try {
+                await program.methods
+                    .collectConcentratedProtocolFees()
+                    .accounts(collectAccounts(bob.publicKey) as any)
+                    .signers([bob])
+                    .rpc();
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Moves concentrated protocol fees to the treasury", async () => {
+            const poolBefore = await program.account.concentratedPool.fetch(clPool);
+            const treasuryA_before = await getTokenBalance(treasuryA);
+
+            await program.methods
+                .collectConcentratedProtocolFees()
+                .accounts(collectAccounts(intializer.publicKey) as any)
+                .signers([intializer])
+                .rpc();
+
+            const poolAfter = await program.account.concentratedPool.fetch(clPool);
+            assert.equal(await getTokenBalance(treasuryA) - treasuryA_before, poolBefore.protocolFeesA.toNumber(), "Treasury A mismatch");
+            assert.ok(poolAfter.protocolFeesA.isZero() && poolAfter.protocolFeesB.isZero(), "Protocol fees were not reset");
+        });
+
+        it("Lets only the config admin change a concentrated pool's price impact limit", async () => {
+            const setClLimit = (admin: Keypair, maxPriceImpactBps: number) => program.methods
+                .setConcentratedMaxPriceImpact(maxPriceImpactBps)
+                .accounts({ config: configPda, pool: clPool, admin: admin.publicKey } as any)
+                .signers([admin])
+                .rpc();
+
+            try {
+                await setClLimit(alice, 500);
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+            try {
+                await setClLimit(intializer, 10_001);
+                assert.fail("Should have failed due to an invalid limit");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidPriceImpactLimit", "Expected InvalidPriceImpactLimit error");
+            }
+
+            await setClLimit(intializer, 500);
+            assert.equal((await program.account.concentratedPool.fetch(clPool)).maxPriceImpactBps, 500, "Price impact limit mismatch");
+            // Back to the uncapped limit the pool was created with
+            await setClLimit(intializer, 0);
+            assert.equal((await program.account.concentratedPool.fetch(clPool)).maxPriceImpactBps, 0, "Price impact limit should be disabled");
+        });
+
+        it("Blocks concentrated swaps and deposits but not withdrawals while the pool is paused", async () => {
+            const setClPaused = (admin: Keypair, paused: boolean) => program.methods
+                .setConcentratedPoolPaused(paused)
+                .accounts({ config: configPda, pool: clPool, admin: admin.publicKey } as any)
+                .signers([admin])
+                .rpc();
+
+            try {
+                await setClPaused(alice, true);
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+
+            await setClPaused(intializer, true);
+            try {
+                const poolAccount = await program.account.concentratedPool.fetch(clPool);
+                assert.isTrue(poolAccount.paused, "Pool should be paused");
+
+                for (const action of [
+                    () => program.methods
+                        .swapConcentrated(new BN(10 ** decimals), new BN(1), null)
+                        .accounts(swapAccounts() as any)
+                        .remainingAccounts([{ pubkey: tickArrayPda(-ticksPerArray), isWritable: true, isSigner: false }])
+                        .signers([alice])
+                        .rpc(),
+                    () => program.methods
+                        .increaseLiquidity(new BN(1000), maxDeposit, maxDeposit)
+                        .accounts(modifyAccounts(alice.publicKey, -1024, 1024) as any)
+                        .signers([alice])
+                        .rpc(),
+                ]) {
+                    try {
+                        await action();
+                        assert.fail("Should have failed while paused");
+                    } catch (e) {
+                        assert.include(e.toString(), "Paused", "Expected Paused error");
+                    }
+                }
+
+                await program.methods
+                    .decreaseLiquidity(new BN(1000), new BN(0), new BN(0))
+                    .accounts(modifyAccounts(alice.publicKey, -1024, 1024) as any)
+                    .signers([alice])
+                    .rpc();
+            } finally {
+                await setClPaused(intializer, false);
+            }
+        });
+
+        it("Withdraws a position with the fees it earned", async () => {
+            const aliceA_before = await getTokenBalance(aliceA);
+            const aliceB_before = await getTokenBalance(aliceB);
+
+            await program.methods
+                .decreaseLiquidity(narrowLiquidity, new BN(0), new BN(0))
+                .accounts(modifyAccounts(alice.publicKey, -128, 128) as any)
+                .signers([alice])
+                .rpc();
+
+            const position = await program.account.position.fetch(positionPda(alice.publicKey, -128, 128));
+            assert.ok(position.liquidity.isZero(), "Position should be empty");
+            assert.ok(position.feesOwedA.isZero() && position.feesOwedB.isZero(), "Fees should be paid out");
+            // The price is below the range, so the position is all Token A, and the
+            // A -> B swap only paid fees in Token A
+            assert.ok(await getTokenBalance(aliceA) - aliceA_before > 0, "No Token A withdrawn");
+            assert.equal(await getTokenBalance(aliceB), aliceB_before, "No Token B expected");
+        });
+
+        it("Fails to open a position with an inverted range", async () => {
+            try {
+                await program.methods
+                    .openPosition(128, -128)
+                    .accounts({
+                        pool: clPool,
+                        position: positionPda(alice.publicKey, 128, -128),
+                        owner: alice.publicKey,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an invalid tick range");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidTickRange", "Expected InvalidTickRange error");
+            }
+        });
+
+        it("Fails when someone else changes a position", async () => {
+            try {
+                await program.methods
+                    .decreaseLiquidity(new BN(1), new BN(0), new BN(0))
+                    .accounts({ ...modifyAccounts(alice.publicKey, -1024, 1024), owner: bob.publicKey } as any)
+                    .signers([bob])
+                    .rpc();
+                assert.fail("Should have failed due to unauthorized signer");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+    });
 })
\ No newline at end of file